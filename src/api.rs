@@ -1,26 +1,47 @@
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     http::StatusCode,
-    response::Json,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
     routing::{get, post},
     Router,
 };
+use futures_util::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 use std::{path::PathBuf, sync::Arc};
-use tokio::{fs, sync::RwLock};
+use tokio::{fs, sync::broadcast};
 use uuid::Uuid;
 use wasmtime::Engine;
 
-use crate::{db, game, simulation::SimulationManager};
+use crate::{
+    cache::{self, ComponentCache},
+    db,
+    events::{QueueSnapshot, SimulationEvent},
+    gauntlet,
+    head_to_head::{self, HeadToHeadMatrix, MatrixFilter},
+    ladder::{Ladder, LadderStanding},
+    ratings,
+    recording::{GameEvent, RecordingPolicy},
+    rules::RuleSet,
+    simulation::{self, Priority, SimulationManager, SimulationSettings},
+    stats::{self, EarlyStopping},
+    strategy::Isolation,
+    tournament::{self, TournamentFormat, TournamentPlan},
+    wasm,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
     pub engine: Arc<Engine>,
     pub bots_dir: PathBuf,
-    pub simulation_manager: Arc<RwLock<SimulationManager>>,
+    pub component_cache: Arc<ComponentCache>,
+    pub simulation_manager: Arc<SimulationManager>,
+    /// `None` unless the server was started with the ladder enabled
+    pub ladder: Option<Arc<Ladder>>,
 }
 
 #[derive(Serialize)]
@@ -29,18 +50,102 @@ struct BotResponse {
     name: String,
     description: Option<String>,
     created_at: String,
+    world_version: Option<String>,
 }
 
 #[derive(Serialize)]
 struct UploadBotResponse {
     id: String,
     message: String,
+    world_version: Option<String>,
+    /// Whether the bot entered the ladder
+    ladder: bool,
+}
+
+#[derive(Serialize)]
+struct LadderActionResponse {
+    bot_id: String,
+    message: String,
 }
 
 #[derive(Deserialize)]
 struct StartSimulationRequest {
     bot_ids: Vec<String>,
+    #[serde(flatten)]
+    options: SimulationOptions,
+}
+
+/// How to run a simulation, as given when starting one
+#[derive(Deserialize)]
+struct SimulationOptions {
     num_games: u32,
+    /// Seed for dice, seat order and bot randomness; generated when omitted
+    seed: Option<u64>,
+    /// Rule preset name or a (partial) rule set; classic rules when omitted
+    rules: Option<RulesSpec>,
+    /// Which games to record for replay; none when omitted
+    record: Option<RecordingPolicy>,
+    /// How often bots start over from a fresh instance; persistent when omitted
+    isolation: Option<Isolation>,
+    /// Worker threads to split the games across; one per CPU core when omitted
+    workers: Option<usize>,
+    /// Queue priority; normal when omitted
+    priority: Option<Priority>,
+    /// When to stop before playing every game; all games are played when omitted
+    early_stopping: Option<EarlyStopping>,
+    /// CPU budget per decision in fuel units; a safe default when omitted
+    fuel_per_decision: Option<u64>,
+    /// CPU budget per bot for the whole run in fuel units; unlimited when omitted
+    fuel_per_simulation: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CreateTournamentRequest {
+    name: Option<String>,
+    bot_ids: Vec<String>,
+    /// Round robin when omitted
+    #[serde(default)]
+    format: TournamentFormat,
+    /// Bots at the table in each match; two when omitted
+    table_size: Option<usize>,
+    /// Rounds a Swiss tournament plays; enough to separate the field when omitted
+    rounds: Option<usize>,
+    /// How each match is run; `num_games` is the number of games per match
+    #[serde(flatten)]
+    options: SimulationOptions,
+}
+
+#[derive(Deserialize)]
+struct CreateSuiteRequest {
+    name: String,
+    bot_ids: Vec<String>,
+    /// How each match is run; `num_games` is the number of games per match
+    #[serde(flatten)]
+    options: SimulationOptions,
+}
+
+#[derive(Deserialize)]
+struct RunGauntletRequest {
+    bot_id: String,
+    /// The suite of built-in reference bots when omitted
+    suite_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HeadToHeadQuery {
+    /// Only simulations completed at or after this date or date-time (UTC)
+    from: Option<String>,
+    /// Only simulations completed before this date or date-time (UTC)
+    to: Option<String>,
+    /// Rule preset name or a JSON rule set; simulations under any rules when omitted
+    rules: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RulesSpec {
+    Preset(String),
+    Custom(RuleSet),
 }
 
 #[derive(Serialize)]
@@ -49,6 +154,70 @@ struct StartSimulationResponse {
     message: String,
 }
 
+#[derive(Serialize)]
+struct CreateTournamentResponse {
+    tournament_id: String,
+    simulation_ids: Vec<String>,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct TournamentSummary {
+    id: String,
+    name: Option<String>,
+    format: String,
+    table_size: i64,
+    /// Rounds the tournament is played over
+    total_rounds: i64,
+    #[serde(flatten)]
+    progress: tournament::TournamentProgress,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+struct TournamentStandingsResponse {
+    #[serde(flatten)]
+    tournament: TournamentSummary,
+    standings: Vec<tournament::TournamentStanding>,
+}
+
+#[derive(Serialize)]
+struct TournamentRoundsResponse {
+    #[serde(flatten)]
+    tournament: TournamentSummary,
+    rounds: Vec<tournament::TournamentRound>,
+}
+
+#[derive(Serialize)]
+struct SuiteResponse {
+    id: String,
+    name: String,
+    bots: Vec<SuiteBot>,
+    games_per_match: u32,
+    /// The match against the bot at index `i` is played with this seed plus `i`
+    seed: u64,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+struct SuiteBot {
+    bot_id: String,
+    bot_name: String,
+}
+
+#[derive(Serialize)]
+struct RunGauntletResponse {
+    gauntlet_id: String,
+    simulation_ids: Vec<String>,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct SimulationActionResponse {
+    simulation_id: String,
+    message: String,
+}
+
 #[derive(Serialize)]
 struct SimulationStatusResponse {
     id: String,
@@ -56,6 +225,17 @@ struct SimulationStatusResponse {
     num_games: u32,
     games_completed: u32,
     memory_limit_mb: Option<u32>,
+    seed: Option<u64>,
+    rules: Option<RuleSet>,
+    record: Option<RecordingPolicy>,
+    isolation: Option<Isolation>,
+    workers: Option<i64>,
+    priority: Option<String>,
+    early_stopping: Option<EarlyStopping>,
+    /// Why the simulation completed before playing every game
+    stop_reason: Option<String>,
+    fuel_per_decision: Option<i64>,
+    fuel_per_simulation: Option<i64>,
     created_at: String,
     started_at: Option<String>,
     completed_at: Option<String>,
@@ -68,6 +248,9 @@ struct SimulationResultsResponse {
     status: String,
     num_games: u32,
     results: Vec<ParticipantResult>,
+    /// Paired tests of every two bots' money per game; empty for simulations
+    /// run before the variances were tracked
+    comparisons: Vec<stats::Comparison>,
     completed_at: Option<String>,
 }
 
@@ -81,6 +264,72 @@ struct ParticipantResult {
     average_money_per_game: f64,
     peak_memory_bytes: Option<i64>,
     disqualified: Option<bool>,
+    disqualification_reason: Option<String>,
+    fuel_consumed: Option<i64>,
+    /// Money per game over the games played, with its uncertainty
+    money_per_game: Option<stats::Estimate>,
+    win_rate: Option<stats::Estimate>,
+}
+
+/// Every bot's cumulative totals after `games_completed` games
+#[derive(Serialize)]
+struct SnapshotResponse {
+    games_completed: i64,
+    created_at: String,
+    standings: Vec<SnapshotStanding>,
+}
+
+#[derive(Serialize)]
+struct SnapshotStanding {
+    player_index: i32,
+    games_won: i32,
+    total_money: i64,
+    win_rate: f64,
+    average_money_per_game: f64,
+}
+
+#[derive(Serialize)]
+struct RatingResponse {
+    bot_id: String,
+    bot_name: String,
+    mu: f64,
+    sigma: f64,
+    /// `mu - 3 sigma`, what bots are ranked by
+    rating: f64,
+    simulations: i64,
+    updated_at: String,
+}
+
+#[derive(Serialize)]
+struct RatingHistoryItem {
+    simulation_id: String,
+    rank: i64,
+    num_bots: i64,
+    mu_before: f64,
+    sigma_before: f64,
+    mu: f64,
+    sigma: f64,
+    rating: f64,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+struct RecomputeRatingsResponse {
+    simulations_rated: usize,
+}
+
+#[derive(Serialize)]
+struct RecordedGameSummary {
+    game_index: i64,
+    winner_index: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct RecordedGameResponse {
+    simulation_id: String,
+    game_index: i64,
+    winner_index: Option<i32>,
+    events: Vec<GameEvent>,
 }
 
 #[derive(Serialize)]
@@ -117,12 +366,51 @@ struct WinnerInfo {
     total_money: i64,
 }
 
+#[derive(sqlx::FromRow)]
+struct RatingWithBotName {
+    bot_id: String,
+    bot_name: String,
+    mu: f64,
+    sigma: f64,
+    simulations: i64,
+    updated_at: String,
+}
+
 pub fn create_router(state: AppState) -> Router {
     Router::new()
         .route("/bots", post(upload_bot).get(list_bots))
+        .route("/bots/:id/rating-history", get(get_rating_history))
+        .route("/bots/:id/gauntlets", get(list_bot_gauntlets))
+        .route("/benchmark-suites", post(create_suite).get(list_suites))
+        .route("/gauntlets", post(run_gauntlet))
+        .route("/gauntlets/:id", get(get_gauntlet))
+        .route("/ladder", get(get_ladder))
+        .route("/ladder/events", get(ladder_events))
+        .route(
+            "/ladder/bots/:id",
+            post(enter_ladder).delete(withdraw_from_ladder),
+        )
+        .route("/head-to-head", get(get_head_to_head))
+        .route("/ratings", get(list_ratings))
+        .route("/ratings/recompute", post(recompute_ratings))
         .route("/simulations", post(start_simulation).get(list_simulations))
         .route("/simulations/:id", get(get_simulation_status))
         .route("/simulations/:id/results", get(get_simulation_results))
+        .route("/simulations/:id/cancel", post(cancel_simulation))
+        .route("/simulations/:id/pause", post(pause_simulation))
+        .route("/simulations/:id/resume", post(resume_simulation))
+        .route("/simulations/:id/events", get(simulation_events))
+        .route("/simulations/:id/snapshots", get(list_snapshots))
+        .route("/simulations/:id/games", get(list_recorded_games))
+        .route("/simulations/:id/games/:game_index", get(get_recorded_game))
+        .route("/queue", get(get_queue))
+        .route("/queue/events", get(queue_events))
+        .route(
+            "/tournaments",
+            post(create_tournament).get(list_tournaments),
+        )
+        .route("/tournaments/:id/standings", get(get_tournament_standings))
+        .route("/tournaments/:id/rounds", get(get_tournament_rounds))
         .with_state(state)
 }
 
@@ -133,6 +421,7 @@ async fn upload_bot(
     let mut name = None;
     let mut description = None;
     let mut wasm_data = None;
+    let mut ladder = None;
 
     while let Some(field) = multipart
         .next_field()
@@ -151,23 +440,38 @@ async fn upload_bot(
             Some("wasm") => {
                 wasm_data = Some(field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?);
             }
+            Some("ladder") => {
+                let text = field.text().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+                ladder = Some(text.parse::<bool>().map_err(|_| StatusCode::BAD_REQUEST)?);
+            }
             _ => {}
         }
     }
 
     let name = name.ok_or(StatusCode::BAD_REQUEST)?;
     let wasm_data = wasm_data.ok_or(StatusCode::BAD_REQUEST)?;
+    // New bots enter the ladder, when it is enabled, unless asked not to
+    let ladder = match (&state.ladder, ladder) {
+        (Some(ladder), None | Some(true)) => Some(ladder),
+        (None, Some(true)) => return Err(StatusCode::BAD_REQUEST),
+        _ => None,
+    };
 
-    // Validate WASM component
-    let _ = game::WasmStrategy::new(&state.engine, &wasm_data).map_err(|e| {
-        tracing::error!("WASM validation failed: {e}");
-        StatusCode::UNPROCESSABLE_ENTITY
-    })?;
+    // Validate WASM component; compiling through the cache means simulations
+    // never have to compile it again
+    let world_version = state
+        .component_cache
+        .component(&state.engine, &wasm_data)
+        .and_then(|component| {
+            wasm::WasmStrategy::from_component(&state.engine, &component, rand::random())
+        })
+        .map_err(|e| {
+            tracing::error!("WASM validation failed: {e}");
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?
+        .world_version();
 
-    // Calculate hash
-    let mut hasher = Sha256::new();
-    hasher.update(&wasm_data);
-    let hash = format!("{:x}", hasher.finalize());
+    let hash = cache::wasm_hash(&wasm_data);
 
     // Check if bot with same hash already exists
     let existing = sqlx::query_as::<_, db::Bot>("SELECT * FROM bots WHERE wasm_hash = ?")
@@ -180,6 +484,8 @@ async fn upload_bot(
         return Ok(Json(UploadBotResponse {
             id: bot.id,
             message: "Bot with identical WASM already exists".to_string(),
+            world_version: bot.world_version,
+            ladder: false,
         }));
     }
 
@@ -194,20 +500,34 @@ async fn upload_bot(
 
     // Save to database
     sqlx::query(
-        "INSERT INTO bots (id, name, description, wasm_hash, file_path) VALUES (?, ?, ?, ?, ?)",
+        "INSERT INTO bots (id, name, description, wasm_hash, file_path, world_version)
+         VALUES (?, ?, ?, ?, ?, ?)",
     )
     .bind(&bot_id)
     .bind(&name)
     .bind(&description)
     .bind(&hash)
     .bind(file_path.to_string_lossy().as_ref())
+    .bind(world_version.as_str())
     .execute(&state.pool)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    if let Some(ladder) = ladder {
+        let bot = find_bots(&state.pool, std::slice::from_ref(&bot_id))
+            .await?
+            .remove(0);
+        ladder
+            .enter(&bot)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
     Ok(Json(UploadBotResponse {
         id: bot_id,
         message: "Bot uploaded successfully".to_string(),
+        world_version: Some(world_version.to_string()),
+        ladder: ladder.is_some(),
     }))
 }
 
@@ -224,12 +544,196 @@ async fn list_bots(State(state): State<AppState>) -> Result<Json<Vec<BotResponse
             name: bot.name,
             description: bot.description,
             created_at: bot.created_at,
+            world_version: bot.world_version,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+async fn get_ladder(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LadderStanding>>, StatusCode> {
+    let ladder = state.ladder.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ladder.leaderboard()))
+}
+
+/// Streams the leaderboard as server-sent events, starting with the current
+/// one and again whenever a ladder match finishes or a bot enters or leaves
+async fn ladder_events(
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let ladder = state.ladder.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let events = stream::unfold(
+        (ladder.subscribe(), true),
+        |(mut receiver, first)| async move {
+            if !first && receiver.changed().await.is_err() {
+                return None;
+            }
+            let leaderboard = receiver.borrow_and_update().clone();
+            let event = Event::default()
+                .event("leaderboard")
+                .json_data(&leaderboard);
+            Some((event, (receiver, false)))
+        },
+    );
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn enter_ladder(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<LadderActionResponse>, StatusCode> {
+    let ladder = state.ladder.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let bot = find_bots(&state.pool, std::slice::from_ref(&id))
+        .await?
+        .remove(0);
+    ladder
+        .enter(&bot)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(LadderActionResponse {
+        bot_id: id,
+        message: "Bot entered the ladder".to_string(),
+    }))
+}
+
+async fn withdraw_from_ladder(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<LadderActionResponse>, StatusCode> {
+    let ladder = state.ladder.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let withdrawn = ladder
+        .withdraw(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !withdrawn {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(LadderActionResponse {
+        bot_id: id,
+        message: "Bot left the ladder".to_string(),
+    }))
+}
+
+async fn list_ratings(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RatingResponse>>, StatusCode> {
+    let rows = sqlx::query_as::<_, RatingWithBotName>(
+        "SELECT r.*, b.name AS bot_name FROM bot_ratings r JOIN bots b ON b.id = r.bot_id",
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut response: Vec<RatingResponse> = rows
+        .into_iter()
+        .map(|rating| RatingResponse {
+            rating: ratings::Rating {
+                mu: rating.mu,
+                sigma: rating.sigma,
+            }
+            .conservative(),
+            bot_id: rating.bot_id,
+            bot_name: rating.bot_name,
+            mu: rating.mu,
+            sigma: rating.sigma,
+            simulations: rating.simulations,
+            updated_at: rating.updated_at,
         })
         .collect();
+    response.sort_by(|a, b| b.rating.total_cmp(&a.rating));
 
     Ok(Json(response))
 }
 
+async fn get_rating_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<RatingHistoryItem>>, StatusCode> {
+    sqlx::query_as::<_, db::Bot>("SELECT * FROM bots WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let history = sqlx::query_as::<_, db::RatingHistoryEntry>(
+        "SELECT * FROM rating_history WHERE bot_id = ? ORDER BY id",
+    )
+    .bind(&id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        history
+            .into_iter()
+            .map(|entry| RatingHistoryItem {
+                rating: ratings::Rating {
+                    mu: entry.mu,
+                    sigma: entry.sigma,
+                }
+                .conservative(),
+                simulation_id: entry.simulation_id,
+                rank: entry.rank,
+                num_bots: entry.num_bots,
+                mu_before: entry.mu_before,
+                sigma_before: entry.sigma_before,
+                mu: entry.mu,
+                sigma: entry.sigma,
+                created_at: entry.created_at,
+            })
+            .collect(),
+    ))
+}
+
+async fn get_head_to_head(
+    State(state): State<AppState>,
+    Query(query): Query<HeadToHeadQuery>,
+) -> Result<Json<HeadToHeadMatrix>, StatusCode> {
+    let rules = match query.rules {
+        None => None,
+        Some(spec) => Some(match RuleSet::preset(&spec) {
+            Some(rules) => rules,
+            None => serde_json::from_str(&spec).map_err(|_| StatusCode::BAD_REQUEST)?,
+        }),
+    };
+    let filter = MatrixFilter {
+        from: time_bound(&state.pool, query.from).await?,
+        to: time_bound(&state.pool, query.to).await?,
+        rules,
+    };
+
+    let matrix = head_to_head::matrix(&state.pool, &filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(matrix))
+}
+
+/// Reads an optional date or date-time from a query; 400 if it isn't one
+async fn time_bound(pool: &SqlitePool, time: Option<String>) -> Result<Option<String>, StatusCode> {
+    match time {
+        None => Ok(None),
+        Some(time) => head_to_head::parse_time(pool, &time)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map(Some)
+            .ok_or(StatusCode::BAD_REQUEST),
+    }
+}
+
+async fn recompute_ratings(
+    State(state): State<AppState>,
+) -> Result<Json<RecomputeRatingsResponse>, StatusCode> {
+    let simulations_rated = ratings::recompute(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(RecomputeRatingsResponse { simulations_rated }))
+}
+
 async fn start_simulation(
     State(state): State<AppState>,
     Json(request): Json<StartSimulationRequest>,
@@ -237,62 +741,344 @@ async fn start_simulation(
     if request.bot_ids.is_empty() {
         return Err(StatusCode::BAD_REQUEST);
     }
+    let settings = simulation_settings(request.options)?;
+    let bots = find_bots(&state.pool, &request.bot_ids).await?;
 
-    if request.num_games <= 0 || request.num_games > 1_000_000 {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    let simulation_id = state
+        .simulation_manager
+        .create(bots, settings)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Verify all bots exist
-    let mut bots = Vec::new();
-    for bot_id in &request.bot_ids {
-        let bot = sqlx::query_as::<_, db::Bot>("SELECT * FROM bots WHERE id = ?")
-            .bind(bot_id)
-            .fetch_optional(&state.pool)
+    Ok(Json(StartSimulationResponse {
+        simulation_id,
+        message: "Simulation queued successfully".to_string(),
+    }))
+}
+
+async fn create_tournament(
+    State(state): State<AppState>,
+    Json(request): Json<CreateTournamentRequest>,
+) -> Result<Json<CreateTournamentResponse>, StatusCode> {
+    let plan = TournamentPlan {
+        name: request.name,
+        format: request.format,
+        table_size: request.table_size.unwrap_or(2),
+        rounds: request.rounds,
+        settings: simulation_settings(request.options)?,
+    };
+    plan.validate(&request.bot_ids)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let bots = find_bots(&state.pool, &request.bot_ids).await?;
+
+    let tournament_id = tournament::create(&state.pool, &state.simulation_manager, plan, bots)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let simulation_ids = sqlx::query_scalar(
+        "SELECT simulation_id FROM tournament_matches WHERE tournament_id = ? ORDER BY match_index",
+    )
+    .bind(&tournament_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(CreateTournamentResponse {
+        tournament_id,
+        simulation_ids,
+        message: "Tournament matches queued successfully".to_string(),
+    }))
+}
+
+async fn list_tournaments(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TournamentSummary>>, StatusCode> {
+    let tournaments =
+        sqlx::query_as::<_, db::Tournament>("SELECT * FROM tournaments ORDER BY created_at DESC")
+            .fetch_all(&state.pool)
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-            .ok_or(StatusCode::NOT_FOUND)?;
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        bots.push(bot);
+    let mut response = Vec::new();
+    for tournament in tournaments {
+        response.push(tournament_summary(&state.pool, tournament).await?);
     }
+    Ok(Json(response))
+}
 
-    let simulation_id = Uuid::new_v4().to_string();
+async fn get_tournament_standings(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TournamentStandingsResponse>, StatusCode> {
+    let tournament = find_tournament(&state.pool, &id).await?;
+    let standings = tournament::standings(&state.pool, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Create simulation record with memory limit
-    let memory_limit_mb = 200u32;
-    sqlx::query(
-        "INSERT INTO simulations (id, status, num_games, memory_limit_mb) VALUES (?, ?, ?, ?)",
+    Ok(Json(TournamentStandingsResponse {
+        tournament: tournament_summary(&state.pool, tournament).await?,
+        standings,
+    }))
+}
+
+async fn get_tournament_rounds(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TournamentRoundsResponse>, StatusCode> {
+    let tournament = find_tournament(&state.pool, &id).await?;
+    let rounds = tournament::rounds(&state.pool, &tournament)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(TournamentRoundsResponse {
+        tournament: tournament_summary(&state.pool, tournament).await?,
+        rounds,
+    }))
+}
+
+async fn find_tournament(pool: &SqlitePool, id: &str) -> Result<db::Tournament, StatusCode> {
+    sqlx::query_as::<_, db::Tournament>("SELECT * FROM tournaments WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn tournament_summary(
+    pool: &SqlitePool,
+    tournament: db::Tournament,
+) -> Result<TournamentSummary, StatusCode> {
+    let progress = tournament::progress(pool, &tournament)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(TournamentSummary {
+        id: tournament.id,
+        name: tournament.name,
+        format: tournament.format,
+        table_size: tournament.table_size,
+        total_rounds: tournament.rounds,
+        progress,
+        created_at: tournament.created_at,
+    })
+}
+
+async fn create_suite(
+    State(state): State<AppState>,
+    Json(request): Json<CreateSuiteRequest>,
+) -> Result<Json<SuiteResponse>, StatusCode> {
+    if request.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    gauntlet::validate_suite(&request.bot_ids).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let settings = simulation_settings(request.options)?;
+    let bots = find_bots(&state.pool, &request.bot_ids).await?;
+
+    let suite_id = gauntlet::create_suite(&state.pool, &request.name, &bots, &settings)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let suite = find_suite(&state.pool, &suite_id).await?;
+    Ok(Json(suite_response(&state.pool, suite).await?))
+}
+
+async fn list_suites(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SuiteResponse>>, StatusCode> {
+    let suites = sqlx::query_as::<_, db::BenchmarkSuite>(
+        "SELECT * FROM benchmark_suites ORDER BY created_at DESC",
     )
-    .bind(&simulation_id)
-    .bind("pending")
-    .bind(request.num_games)
-    .bind(memory_limit_mb)
-    .execute(&state.pool)
+    .fetch_all(&state.pool)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Create participant records
-    for (index, bot) in bots.iter().enumerate() {
-        sqlx::query(
-            "INSERT INTO simulation_participants (simulation_id, bot_id, player_index) VALUES (?, ?, ?)"
-        )
-        .bind(&simulation_id)
-        .bind(&bot.id)
-        .bind(index as i32)
-        .execute(&state.pool)
+    let mut response = Vec::new();
+    for suite in suites {
+        response.push(suite_response(&state.pool, suite).await?);
+    }
+    Ok(Json(response))
+}
+
+async fn run_gauntlet(
+    State(state): State<AppState>,
+    Json(request): Json<RunGauntletRequest>,
+) -> Result<Json<RunGauntletResponse>, StatusCode> {
+    let suite_id = request
+        .suite_id
+        .unwrap_or_else(|| gauntlet::REFERENCE_SUITE_ID.to_string());
+    let suite = find_suite(&state.pool, &suite_id).await?;
+    let bot = find_bots(&state.pool, &[request.bot_id]).await?.remove(0);
+    let opponents = gauntlet::suite_bots(&state.pool, &suite.id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // A bot can't be measured against itself
+    if opponents.iter().any(|opponent| opponent.id == bot.id) {
+        return Err(StatusCode::BAD_REQUEST);
     }
 
-    // Queue simulation for execution
-    let mut manager = state.simulation_manager.write().await;
-    manager.queue_simulation(simulation_id.clone(), bots, request.num_games);
+    let gauntlet_id = gauntlet::run(&state.pool, &state.simulation_manager, &suite, &bot)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(StartSimulationResponse {
-        simulation_id,
-        message: "Simulation queued successfully".to_string(),
+    let simulation_ids = sqlx::query_scalar(
+        "SELECT simulation_id FROM gauntlet_matches WHERE gauntlet_id = ? ORDER BY entry_index",
+    )
+    .bind(&gauntlet_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RunGauntletResponse {
+        gauntlet_id,
+        simulation_ids,
+        message: "Gauntlet matches queued successfully".to_string(),
     }))
 }
 
+async fn get_gauntlet(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<gauntlet::Scorecard>, StatusCode> {
+    let gauntlet = sqlx::query_as::<_, db::Gauntlet>("SELECT * FROM gauntlets WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let scorecard = gauntlet::scorecard(&state.pool, gauntlet)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(scorecard))
+}
+
+async fn list_bot_gauntlets(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<gauntlet::Scorecard>>, StatusCode> {
+    find_bots(&state.pool, std::slice::from_ref(&id)).await?;
+
+    let gauntlets = sqlx::query_as::<_, db::Gauntlet>(
+        "SELECT * FROM gauntlets WHERE bot_id = ? ORDER BY created_at DESC",
+    )
+    .bind(&id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut response = Vec::new();
+    for gauntlet in gauntlets {
+        response.push(
+            gauntlet::scorecard(&state.pool, gauntlet)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+    Ok(Json(response))
+}
+
+async fn find_suite(pool: &SqlitePool, id: &str) -> Result<db::BenchmarkSuite, StatusCode> {
+    sqlx::query_as::<_, db::BenchmarkSuite>("SELECT * FROM benchmark_suites WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn suite_response(
+    pool: &SqlitePool,
+    suite: db::BenchmarkSuite,
+) -> Result<SuiteResponse, StatusCode> {
+    let settings: SimulationSettings =
+        serde_json::from_str(&suite.settings).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let bots = gauntlet::suite_bots(pool, &suite.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(SuiteResponse {
+        id: suite.id,
+        name: suite.name,
+        bots: bots
+            .into_iter()
+            .map(|bot| SuiteBot {
+                bot_id: bot.id,
+                bot_name: bot.name,
+            })
+            .collect(),
+        games_per_match: settings.num_games,
+        seed: settings.seed,
+        created_at: suite.created_at,
+    })
+}
+
+/// Checks the options a simulation was requested with and fills in defaults
+fn simulation_settings(options: SimulationOptions) -> Result<SimulationSettings, StatusCode> {
+    if options.num_games == 0 || options.num_games > 1_000_000 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rules = match options.rules {
+        None => RuleSet::default(),
+        Some(RulesSpec::Preset(name)) => RuleSet::preset(&name).ok_or(StatusCode::BAD_REQUEST)?,
+        Some(RulesSpec::Custom(rules)) => rules,
+    };
+    rules.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let recording = options.record.unwrap_or_default();
+    recording
+        .validate(options.num_games as u64)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let isolation = options.isolation.unwrap_or_default();
+    isolation.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let workers = options.workers.unwrap_or_else(simulation::default_workers);
+    if workers == 0 || workers > simulation::MAX_WORKERS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(early_stopping) = &options.early_stopping {
+        early_stopping
+            .validate()
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+    }
+
+    let fuel_per_decision = options
+        .fuel_per_decision
+        .unwrap_or(wasm::DEFAULT_FUEL_PER_DECISION);
+    if fuel_per_decision == 0 || options.fuel_per_simulation == Some(0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(SimulationSettings {
+        num_games: options.num_games,
+        seed: options.seed.unwrap_or_else(simulation::random_seed),
+        rules,
+        recording,
+        isolation,
+        workers,
+        priority: options.priority.unwrap_or_default(),
+        early_stopping: options.early_stopping,
+        fuel_per_decision: Some(fuel_per_decision),
+        fuel_per_simulation: options.fuel_per_simulation,
+    })
+}
+
+/// Looks up bots by id, in the given order; 404 if any doesn't exist
+async fn find_bots(pool: &SqlitePool, bot_ids: &[String]) -> Result<Vec<db::Bot>, StatusCode> {
+    let mut bots = Vec::new();
+    for bot_id in bot_ids {
+        let bot = sqlx::query_as::<_, db::Bot>("SELECT * FROM bots WHERE id = ?")
+            .bind(bot_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        bots.push(bot);
+    }
+    Ok(bots)
+}
+
 async fn list_simulations(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<SimulationHistoryItem>>, StatusCode> {
@@ -379,6 +1165,28 @@ async fn get_simulation_status(
         num_games: simulation.num_games,
         games_completed: simulation.games_completed,
         memory_limit_mb: simulation.memory_limit_mb,
+        seed: simulation.seed.map(|seed| seed as u64),
+        rules: simulation
+            .rules
+            .as_deref()
+            .and_then(|rules| serde_json::from_str(rules).ok()),
+        record: simulation
+            .recording
+            .as_deref()
+            .and_then(|recording| serde_json::from_str(recording).ok()),
+        isolation: simulation
+            .isolation
+            .as_deref()
+            .and_then(|isolation| serde_json::from_str(isolation).ok()),
+        workers: simulation.workers,
+        priority: simulation.priority,
+        early_stopping: simulation
+            .early_stopping
+            .as_deref()
+            .and_then(|early_stopping| serde_json::from_str(early_stopping).ok()),
+        stop_reason: simulation.stop_reason,
+        fuel_per_decision: simulation.fuel_per_decision,
+        fuel_per_simulation: simulation.fuel_per_simulation,
         created_at: simulation.created_at,
         started_at: simulation.started_at,
         completed_at: simulation.completed_at,
@@ -386,6 +1194,175 @@ async fn get_simulation_status(
     }))
 }
 
+async fn cancel_simulation(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SimulationActionResponse>, StatusCode> {
+    let cancelled = state
+        .simulation_manager
+        .cancel(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !cancelled {
+        return Err(action_not_allowed(&state.pool, &id).await);
+    }
+
+    Ok(Json(SimulationActionResponse {
+        simulation_id: id,
+        message: "Simulation cancelled".to_string(),
+    }))
+}
+
+async fn pause_simulation(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SimulationActionResponse>, StatusCode> {
+    let paused = state
+        .simulation_manager
+        .pause(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !paused {
+        return Err(action_not_allowed(&state.pool, &id).await);
+    }
+
+    Ok(Json(SimulationActionResponse {
+        simulation_id: id,
+        message: "Simulation paused".to_string(),
+    }))
+}
+
+async fn resume_simulation(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SimulationActionResponse>, StatusCode> {
+    let resumed = state
+        .simulation_manager
+        .resume(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !resumed {
+        return Err(action_not_allowed(&state.pool, &id).await);
+    }
+
+    Ok(Json(SimulationActionResponse {
+        simulation_id: id,
+        message: "Simulation queued to resume".to_string(),
+    }))
+}
+
+/// Status for a cancel, pause or resume the simulation's current status doesn't allow
+async fn action_not_allowed(pool: &SqlitePool, id: &str) -> StatusCode {
+    let status = sqlx::query_scalar::<_, String>("SELECT status FROM simulations WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await;
+    match status {
+        Ok(Some(_)) => StatusCode::CONFLICT,
+        Ok(None) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Streams a simulation's status, progress and disqualifications as
+/// server-sent events, starting with its current status and ending once it
+/// completes, fails or is cancelled
+async fn simulation_events(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    // Subscribe before reading the status so no change in between is missed
+    let receiver = state.simulation_manager.subscribe();
+    let status = sqlx::query_scalar::<_, String>("SELECT status FROM simulations WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let initial = SimulationEvent::status(&id, &status);
+    Ok(event_stream(initial, receiver, Some(id), state.pool))
+}
+
+async fn get_queue(State(state): State<AppState>) -> Json<QueueSnapshot> {
+    Json(state.simulation_manager.queue_snapshot())
+}
+
+/// Streams the queue and every simulation's events as server-sent events,
+/// starting with the current queue
+async fn queue_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.simulation_manager.subscribe();
+    let initial = SimulationEvent::Queue(state.simulation_manager.queue_snapshot());
+    event_stream(initial, receiver, None, state.pool)
+}
+
+/// Sends `initial`, then the events from `receiver` about `simulation_id`
+/// (or all of them) until the simulation reaches a final status. Events a
+/// slow client fell too far behind on are skipped, and since the final status
+/// may be among them, the simulation's status is read back from `pool` to end
+/// the stream if it has finished in the meantime.
+fn event_stream(
+    initial: SimulationEvent,
+    receiver: broadcast::Receiver<SimulationEvent>,
+    simulation_id: Option<String>,
+    pool: SqlitePool,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    struct StreamState {
+        pending: Option<SimulationEvent>,
+        receiver: broadcast::Receiver<SimulationEvent>,
+        simulation_id: Option<String>,
+        pool: SqlitePool,
+        done: bool,
+    }
+
+    let state = StreamState {
+        pending: Some(initial),
+        receiver,
+        simulation_id,
+        pool,
+        done: false,
+    };
+    let events = stream::unfold(state, |mut state| async move {
+        let event = match state.pending.take() {
+            Some(event) => event,
+            None if state.done => return None,
+            None => loop {
+                match state.receiver.recv().await {
+                    Ok(event) => match &state.simulation_id {
+                        Some(id) if event.simulation_id() != Some(id.as_str()) => continue,
+                        _ => break event,
+                    },
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let Some(id) = &state.simulation_id else {
+                            continue;
+                        };
+                        let status = sqlx::query_scalar::<_, String>(
+                            "SELECT status FROM simulations WHERE id = ?",
+                        )
+                        .bind(id)
+                        .fetch_optional(&state.pool)
+                        .await;
+                        if let Ok(Some(status)) = status {
+                            let event = SimulationEvent::status(id, &status);
+                            if event.is_final() {
+                                break event;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            },
+        };
+        state.done = state.simulation_id.is_some() && event.is_final();
+        Some((event, state))
+    });
+
+    Sse::new(events.map(|event| Event::default().event(event.name()).json_data(&event)))
+        .keep_alive(KeepAlive::default())
+}
+
 async fn get_simulation_results(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -405,8 +1382,44 @@ async fn get_simulation_results(
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let games = simulation.games_completed as u64;
+    let money_products = participants
+        .iter()
+        .map(|participant| {
+            participant
+                .money_products
+                .as_deref()
+                .and_then(|json| serde_json::from_str::<Vec<f64>>(json).ok())
+                .filter(|products| products.len() == participants.len())
+        })
+        .collect::<Option<Vec<_>>>();
+
+    let mut comparisons = Vec::new();
+    if let Some(products) = &money_products {
+        for a in 0..participants.len() {
+            for b in a + 1..participants.len() {
+                let player = |i: usize| (i, participants[i].total_money as f64, products[i][i]);
+                comparisons.extend(stats::compare(player(a), player(b), products[a][b], games));
+            }
+        }
+    }
+
     let mut results = Vec::new();
-    for participant in participants {
+    for (index, participant) in participants.into_iter().enumerate() {
+        // Totals are only written when a run stops, along with the products
+        let (money_per_game, win_rate) = match &money_products {
+            Some(products) => {
+                let total_money = participant.total_money as f64;
+                // Wins are 0 or 1, so their sum of squares is the number of wins
+                let games_won = participant.games_won as f64;
+                (
+                    stats::Estimate::from_sums(games, total_money, products[index][index]),
+                    stats::Estimate::from_sums(games, games_won, games_won),
+                )
+            }
+            None => (None, None),
+        };
+
         let bot = sqlx::query_as::<_, db::Bot>("SELECT * FROM bots WHERE id = ?")
             .bind(&participant.bot_id)
             .fetch_optional(&state.pool)
@@ -420,9 +1433,15 @@ async fn get_simulation_results(
             player_index: participant.player_index,
             games_won: participant.games_won,
             total_money: participant.total_money,
-            average_money_per_game: participant.total_money as f64 / simulation.num_games as f64,
+            // Cancelled and early-stopped runs play fewer than `num_games`
+            average_money_per_game: participant.total_money as f64
+                / simulation.games_completed.max(1) as f64,
             peak_memory_bytes: participant.peak_memory_bytes,
             disqualified: participant.disqualified,
+            disqualification_reason: participant.disqualification_reason,
+            fuel_consumed: participant.fuel_consumed,
+            money_per_game,
+            win_rate,
         });
     }
 
@@ -431,6 +1450,101 @@ async fn get_simulation_results(
         status: simulation.status,
         num_games: simulation.num_games,
         results,
+        comparisons,
         completed_at: simulation.completed_at,
     }))
 }
+
+async fn list_snapshots(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<SnapshotResponse>>, StatusCode> {
+    sqlx::query_scalar::<_, String>("SELECT status FROM simulations WHERE id = ?")
+        .bind(&id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let rows = sqlx::query_as::<_, db::SimulationSnapshot>(
+        "SELECT * FROM simulation_snapshots
+         WHERE simulation_id = ?
+         ORDER BY games_completed, player_index",
+    )
+    .bind(&id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut snapshots: Vec<SnapshotResponse> = Vec::new();
+    for row in rows {
+        let games = row.games_completed.max(1) as f64;
+        let standing = SnapshotStanding {
+            player_index: row.player_index,
+            games_won: row.games_won,
+            total_money: row.total_money,
+            win_rate: row.games_won as f64 / games,
+            average_money_per_game: row.total_money as f64 / games,
+        };
+        match snapshots.last_mut() {
+            Some(snapshot) if snapshot.games_completed == row.games_completed => {
+                snapshot.standings.push(standing)
+            }
+            _ => snapshots.push(SnapshotResponse {
+                games_completed: row.games_completed,
+                created_at: row.created_at,
+                standings: vec![standing],
+            }),
+        }
+    }
+
+    Ok(Json(snapshots))
+}
+
+async fn list_recorded_games(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<RecordedGameSummary>>, StatusCode> {
+    let records = sqlx::query_as::<_, db::GameRecord>(
+        "SELECT * FROM game_records WHERE simulation_id = ? ORDER BY game_index",
+    )
+    .bind(&id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let response = records
+        .into_iter()
+        .map(|record| RecordedGameSummary {
+            game_index: record.game_index,
+            winner_index: record.winner_index,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+async fn get_recorded_game(
+    State(state): State<AppState>,
+    Path((id, game_index)): Path<(String, i64)>,
+) -> Result<Json<RecordedGameResponse>, StatusCode> {
+    let record = sqlx::query_as::<_, db::GameRecord>(
+        "SELECT * FROM game_records WHERE simulation_id = ? AND game_index = ?",
+    )
+    .bind(&id)
+    .bind(game_index)
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let events: Vec<GameEvent> =
+        serde_json::from_str(&record.events).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RecordedGameResponse {
+        simulation_id: record.simulation_id,
+        game_index: record.game_index,
+        winner_index: record.winner_index,
+        events,
+    }))
+}
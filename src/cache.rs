@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+/// Distinguishes temporary files written by concurrent compiles in this process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Content hash of a WASM file; the `wasm_hash` stored for each bot.
+pub fn wasm_hash(wasm_bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(wasm_bytes);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Compiled components kept on disk so that each bot is compiled once rather
+/// than at every upload and simulation.
+///
+/// Artifacts are stored as `<dir>/<engine-hash>/<wasm_hash>.cwasm`, where the
+/// engine hash covers the wasmtime version and the engine configuration.
+/// Changing either starts a new subdirectory, and stale ones are removed when
+/// the cache is opened.
+pub struct ComponentCache {
+    dir: PathBuf,
+}
+
+impl ComponentCache {
+    pub fn open(engine: &Engine, base_dir: &Path) -> Result<Self> {
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        let engine_hash = format!("{:016x}", hasher.finish());
+
+        // Artifacts from other wasmtime versions or engine configs can't be loaded
+        if let Ok(entries) = fs::read_dir(base_dir) {
+            for entry in entries.flatten() {
+                if entry.file_name() != engine_hash.as_str() {
+                    let _ = fs::remove_dir_all(entry.path());
+                }
+            }
+        }
+
+        let dir = base_dir.join(engine_hash);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create component cache: {}", dir.display()))?;
+        Ok(ComponentCache { dir })
+    }
+
+    /// Returns the compiled component for `wasm_bytes`, compiling and caching
+    /// it on a miss.
+    pub fn component(&self, engine: &Engine, wasm_bytes: &[u8]) -> Result<Component> {
+        let path = self.dir.join(format!("{}.cwasm", wasm_hash(wasm_bytes)));
+
+        if path.exists() {
+            // SAFETY: only `ComponentCache` writes to this directory, and it
+            // only writes the output of `Component::serialize`
+            match unsafe { Component::deserialize_file(engine, &path) } {
+                Ok(component) => return Ok(component),
+                Err(e) => {
+                    tracing::warn!("Discarding cached component {}: {e:#}", path.display());
+                }
+            }
+        }
+
+        let component = Component::from_binary(engine, wasm_bytes)
+            .context("Failed to compile WASM component")?;
+
+        // A failed write only costs a recompile next time. Each write goes to
+        // its own temporary file, so concurrent compiles of the same bot can't
+        // truncate each other's output, and the rename keeps readers from
+        // seeing half a file.
+        let result = component.serialize().and_then(|bytes| {
+            let tmp_path = path.with_extension(format!(
+                "tmp-{}-{}",
+                std::process::id(),
+                TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::write(&tmp_path, bytes)?;
+            if let Err(e) = fs::rename(&tmp_path, &path) {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(e.into());
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to cache compiled component {}: {e:#}",
+                path.display()
+            );
+        }
+
+        Ok(component)
+    }
+}
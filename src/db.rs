@@ -1,8 +1,12 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use sqlx::{sqlite::SqlitePoolOptions, Connection, SqlitePool};
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+use crate::cache::ComponentCache;
+use crate::strategy::{BUILTIN_PREFIX, REFERENCE_BOTS};
+use crate::wasm::WorldVersion;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Bot {
     pub id: String,
     pub name: String,
@@ -10,6 +14,8 @@ pub struct Bot {
     pub wasm_hash: String,
     pub file_path: String,
     pub created_at: String,
+    /// `pig-pen:player` world version the component targets; `None` for built-in bots
+    pub world_version: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -19,6 +25,24 @@ pub struct Simulation {
     pub num_games: u32,
     pub games_completed: u32,
     pub memory_limit_mb: Option<u32>,
+    pub seed: Option<i64>,
+    pub rules: Option<String>,
+    pub fuel_per_decision: Option<i64>,
+    pub fuel_per_simulation: Option<i64>,
+    /// JSON-encoded `Isolation`; persistent when absent
+    pub isolation: Option<String>,
+    /// Worker threads the games were split across; one when absent
+    pub workers: Option<i64>,
+    /// Queue priority (`low`, `normal` or `high`); normal when absent
+    pub priority: Option<String>,
+    /// JSON-encoded `RecordingPolicy`; nothing recorded when absent
+    pub recording: Option<String>,
+    /// JSON-encoded game ranges a paused simulation has left to play
+    pub checkpoint: Option<String>,
+    /// JSON-encoded `EarlyStopping`; every game is played when absent
+    pub early_stopping: Option<String>,
+    /// Why a simulation completed before playing every game
+    pub stop_reason: Option<String>,
     pub created_at: String,
     pub started_at: Option<String>,
     pub completed_at: Option<String>,
@@ -35,6 +59,103 @@ pub struct SimulationParticipant {
     pub peak_memory_bytes: Option<i64>,
     pub avg_memory_bytes: Option<i64>,
     pub disqualified: Option<bool>,
+    pub disqualification_reason: Option<String>,
+    pub fuel_consumed: Option<i64>,
+    /// JSON-encoded sums of this bot's money times each player's money per
+    /// game, by player index; absent for simulations run before it was tracked
+    pub money_products: Option<String>,
+}
+
+/// One bot's cumulative totals part way through a simulation
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SimulationSnapshot {
+    pub simulation_id: String,
+    pub games_completed: i64,
+    pub player_index: i32,
+    pub games_won: i32,
+    pub total_money: i64,
+    pub created_at: String,
+}
+
+/// How one simulation changed a bot's rating
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RatingHistoryEntry {
+    pub id: i64,
+    pub bot_id: String,
+    pub simulation_id: String,
+    /// 0 for the bot that made the most money; tied bots share a rank
+    pub rank: i64,
+    /// Distinct bots in the simulation
+    pub num_bots: i64,
+    pub mu_before: f64,
+    pub sigma_before: f64,
+    pub mu: f64,
+    pub sigma: f64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Tournament {
+    pub id: String,
+    pub name: Option<String>,
+    /// `TournamentFormat` the matches are scheduled by
+    pub format: String,
+    /// Bots at the table in each match
+    pub table_size: i64,
+    /// Rounds the tournament is played over; one for a round robin
+    pub rounds: i64,
+    /// JSON-encoded `SimulationSettings` every match is run with
+    pub settings: String,
+    pub created_at: String,
+}
+
+/// A simulation played as part of a tournament
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TournamentMatch {
+    pub tournament_id: String,
+    pub match_index: i64,
+    /// Rounds count from 1
+    pub round: i64,
+    /// Position within the round, for drawing pairings and brackets
+    pub slot: i64,
+    pub simulation_id: String,
+}
+
+/// A bot that sits out a Swiss round or goes straight through a bracket slot
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TournamentBye {
+    pub tournament_id: String,
+    pub round: i64,
+    pub slot: i64,
+    pub bot_id: String,
+}
+
+/// Bots a gauntlet plays against, and how each match is run
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BenchmarkSuite {
+    pub id: String,
+    pub name: String,
+    /// JSON-encoded `SimulationSettings`; the match against entry `i` is
+    /// played with the seed plus `i`
+    pub settings: String,
+    pub created_at: String,
+}
+
+/// A bot's run against every bot of a benchmark suite
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Gauntlet {
+    pub id: String,
+    pub suite_id: String,
+    pub bot_id: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct GameRecord {
+    pub simulation_id: String,
+    pub game_index: i64,
+    pub winner_index: Option<i32>,
+    pub events: String,
 }
 
 pub async fn create_pool() -> Result<SqlitePool> {
@@ -59,14 +180,22 @@ pub async fn create_pool() -> Result<SqlitePool> {
     .execute(&pool)
     .await?;
 
+    let _ = sqlx::query("ALTER TABLE bots ADD COLUMN world_version TEXT")
+        .execute(&pool)
+        .await;
+
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS simulations (
             id TEXT PRIMARY KEY,
-            status TEXT NOT NULL CHECK (status IN ('pending', 'running', 'completed', 'failed')),
+            status TEXT NOT NULL CHECK (status IN ('pending', 'running', 'paused', 'completed', 'failed', 'cancelled')),
             num_games INTEGER NOT NULL,
             games_completed INTEGER DEFAULT 0,
             memory_limit_mb INTEGER,
+            seed INTEGER,
+            rules TEXT,
+            fuel_per_decision INTEGER,
+            fuel_per_simulation INTEGER,
             created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
             started_at DATETIME,
             completed_at DATETIME,
@@ -84,6 +213,40 @@ pub async fn create_pool() -> Result<SqlitePool> {
     let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN memory_limit_mb INTEGER")
         .execute(&pool)
         .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN seed INTEGER")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN rules TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN fuel_per_decision INTEGER")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN fuel_per_simulation INTEGER")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN isolation TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN workers INTEGER")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN priority TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN recording TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN checkpoint TEXT")
+        .execute(&pool)
+        .await;
+    migrate_simulation_statuses(&pool).await?;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN early_stopping TEXT")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulations ADD COLUMN stop_reason TEXT")
+        .execute(&pool)
+        .await;
 
     sqlx::query(
         r#"
@@ -96,6 +259,9 @@ pub async fn create_pool() -> Result<SqlitePool> {
             peak_memory_bytes INTEGER,
             avg_memory_bytes INTEGER,
             disqualified BOOLEAN DEFAULT FALSE,
+            disqualification_reason TEXT,
+            fuel_consumed INTEGER,
+            money_products TEXT,
             PRIMARY KEY (simulation_id, bot_id, player_index),
             FOREIGN KEY (simulation_id) REFERENCES simulations(id),
             FOREIGN KEY (bot_id) REFERENCES bots(id)
@@ -117,6 +283,364 @@ pub async fn create_pool() -> Result<SqlitePool> {
     )
     .execute(&pool)
     .await;
+    let _ =
+        sqlx::query("ALTER TABLE simulation_participants ADD COLUMN disqualification_reason TEXT")
+            .execute(&pool)
+            .await;
+    let _ = sqlx::query("ALTER TABLE simulation_participants ADD COLUMN fuel_consumed INTEGER")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE simulation_participants ADD COLUMN money_products TEXT")
+        .execute(&pool)
+        .await;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS game_records (
+            simulation_id TEXT NOT NULL,
+            game_index INTEGER NOT NULL,
+            winner_index INTEGER,
+            events TEXT NOT NULL,
+            PRIMARY KEY (simulation_id, game_index),
+            FOREIGN KEY (simulation_id) REFERENCES simulations(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS simulation_snapshots (
+            simulation_id TEXT NOT NULL,
+            games_completed INTEGER NOT NULL,
+            player_index INTEGER NOT NULL,
+            games_won INTEGER NOT NULL,
+            total_money INTEGER NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (simulation_id, games_completed, player_index),
+            FOREIGN KEY (simulation_id) REFERENCES simulations(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS bot_ratings (
+            bot_id TEXT PRIMARY KEY,
+            mu REAL NOT NULL,
+            sigma REAL NOT NULL,
+            simulations INTEGER NOT NULL DEFAULT 0,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS rating_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            bot_id TEXT NOT NULL,
+            simulation_id TEXT NOT NULL,
+            rank INTEGER NOT NULL,
+            num_bots INTEGER NOT NULL,
+            mu_before REAL NOT NULL,
+            sigma_before REAL NOT NULL,
+            mu REAL NOT NULL,
+            sigma REAL NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (simulation_id, bot_id),
+            FOREIGN KEY (bot_id) REFERENCES bots(id),
+            FOREIGN KEY (simulation_id) REFERENCES simulations(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tournaments (
+            id TEXT PRIMARY KEY,
+            name TEXT,
+            format TEXT NOT NULL,
+            table_size INTEGER NOT NULL,
+            rounds INTEGER NOT NULL,
+            settings TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tournament_entrants (
+            tournament_id TEXT NOT NULL,
+            bot_id TEXT NOT NULL,
+            entry_index INTEGER NOT NULL,
+            PRIMARY KEY (tournament_id, bot_id),
+            FOREIGN KEY (tournament_id) REFERENCES tournaments(id),
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tournament_matches (
+            tournament_id TEXT NOT NULL,
+            match_index INTEGER NOT NULL,
+            round INTEGER NOT NULL,
+            slot INTEGER NOT NULL,
+            simulation_id TEXT NOT NULL UNIQUE,
+            PRIMARY KEY (tournament_id, match_index),
+            FOREIGN KEY (tournament_id) REFERENCES tournaments(id),
+            FOREIGN KEY (simulation_id) REFERENCES simulations(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tournament_byes (
+            tournament_id TEXT NOT NULL,
+            round INTEGER NOT NULL,
+            slot INTEGER NOT NULL,
+            bot_id TEXT NOT NULL,
+            PRIMARY KEY (tournament_id, round, slot),
+            FOREIGN KEY (tournament_id) REFERENCES tournaments(id),
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ladder_entrants (
+            bot_id TEXT PRIMARY KEY,
+            joined_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            retired_at DATETIME,
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ladder_matches (
+            simulation_id TEXT PRIMARY KEY,
+            kind TEXT NOT NULL,
+            bot_id TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (simulation_id) REFERENCES simulations(id),
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS benchmark_suites (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            settings TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS benchmark_suite_bots (
+            suite_id TEXT NOT NULL,
+            bot_id TEXT NOT NULL,
+            entry_index INTEGER NOT NULL,
+            PRIMARY KEY (suite_id, entry_index),
+            FOREIGN KEY (suite_id) REFERENCES benchmark_suites(id),
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS gauntlets (
+            id TEXT PRIMARY KEY,
+            suite_id TEXT NOT NULL,
+            bot_id TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (suite_id) REFERENCES benchmark_suites(id),
+            FOREIGN KEY (bot_id) REFERENCES bots(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS gauntlet_matches (
+            gauntlet_id TEXT NOT NULL,
+            entry_index INTEGER NOT NULL,
+            simulation_id TEXT NOT NULL UNIQUE,
+            PRIMARY KEY (gauntlet_id, entry_index),
+            FOREIGN KEY (gauntlet_id) REFERENCES gauntlets(id),
+            FOREIGN KEY (simulation_id) REFERENCES simulations(id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
 
     Ok(pool)
 }
+
+/// Rebuilds the `simulations` table of databases created before simulations
+/// could be paused or cancelled, since SQLite can't change a CHECK constraint
+/// in place.
+async fn migrate_simulation_statuses(pool: &SqlitePool) -> Result<()> {
+    let sql: String = sqlx::query_scalar(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'simulations'",
+    )
+    .fetch_one(pool)
+    .await?;
+    if sql.contains("'cancelled'") {
+        return Ok(());
+    }
+
+    const COLUMNS: &str = "id, status, num_games, games_completed, memory_limit_mb, seed, rules,
+        fuel_per_decision, fuel_per_simulation, created_at, started_at, completed_at,
+        error_message, isolation, workers, priority, recording, checkpoint";
+
+    // The table is referenced by foreign keys, which have to be off while it is
+    // swapped out. The pragma only applies to this connection and has no effect
+    // inside a transaction.
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+    let rebuilt = async {
+        let mut tx = conn.begin().await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE simulations_new (
+                id TEXT PRIMARY KEY,
+                status TEXT NOT NULL CHECK (status IN ('pending', 'running', 'paused', 'completed', 'failed', 'cancelled')),
+                num_games INTEGER NOT NULL,
+                games_completed INTEGER DEFAULT 0,
+                memory_limit_mb INTEGER,
+                seed INTEGER,
+                rules TEXT,
+                fuel_per_decision INTEGER,
+                fuel_per_simulation INTEGER,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                started_at DATETIME,
+                completed_at DATETIME,
+                error_message TEXT,
+                isolation TEXT,
+                workers INTEGER,
+                priority TEXT,
+                recording TEXT,
+                checkpoint TEXT
+            )
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!(
+            "INSERT INTO simulations_new ({COLUMNS}) SELECT {COLUMNS} FROM simulations"
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DROP TABLE simulations")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("ALTER TABLE simulations_new RENAME TO simulations")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+    rebuilt?;
+    Ok(())
+}
+
+/// Makes the native reference bots available like any uploaded bot.
+pub async fn register_builtin_bots(pool: &SqlitePool) -> Result<()> {
+    for (name, description) in REFERENCE_BOTS {
+        let file_path = format!("{}{}", BUILTIN_PREFIX, name);
+        sqlx::query(
+            "INSERT OR IGNORE INTO bots (id, name, description, wasm_hash, file_path)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(format!("builtin-{}", name))
+        .bind(format!("{} (built-in)", name))
+        .bind(description)
+        .bind(&file_path)
+        .bind(&file_path)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Records the world version of bots uploaded before versions were tracked.
+/// Bots whose file can't be read or compiled are left as they are.
+pub async fn backfill_world_versions(
+    pool: &SqlitePool,
+    engine: &wasmtime::Engine,
+    cache: &ComponentCache,
+) -> Result<()> {
+    let bots = sqlx::query_as::<_, Bot>(
+        "SELECT * FROM bots WHERE world_version IS NULL AND file_path NOT LIKE ?",
+    )
+    .bind(format!("{}%", BUILTIN_PREFIX))
+    .fetch_all(pool)
+    .await?;
+
+    for bot in bots {
+        let version = std::fs::read(&bot.file_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| cache.component(engine, &bytes))
+            .and_then(|component| WorldVersion::detect(&component));
+        match version {
+            Ok(version) => {
+                sqlx::query("UPDATE bots SET world_version = ? WHERE id = ?")
+                    .bind(version.as_str())
+                    .bind(&bot.id)
+                    .execute(pool)
+                    .await?;
+            }
+            Err(e) => {
+                tracing::warn!("Could not detect world version of bot {}: {e:#}", bot.id);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::simulation::Priority;
+use crate::strategy::DisqualificationReason;
+
+/// Events a subscriber can fall behind by before it starts missing some
+const EVENT_BUFFER: usize = 1024;
+
+/// Something that happened to the simulation queue or a simulation in it,
+/// streamed to clients as it happens.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SimulationEvent {
+    /// A simulation was queued, started, finished or taken out of the queue
+    Queue(QueueSnapshot),
+    /// A simulation's status changed
+    Status {
+        simulation_id: String,
+        status: String,
+    },
+    /// Periodic update from a running simulation
+    Progress(ProgressUpdate),
+    Disqualified {
+        simulation_id: String,
+        player_index: usize,
+        bot_id: String,
+        bot_name: String,
+        reason: DisqualificationReason,
+    },
+}
+
+impl SimulationEvent {
+    pub fn status(simulation_id: &str, status: &str) -> Self {
+        SimulationEvent::Status {
+            simulation_id: simulation_id.to_string(),
+            status: status.to_string(),
+        }
+    }
+
+    /// SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimulationEvent::Queue(_) => "queue",
+            SimulationEvent::Status { .. } => "status",
+            SimulationEvent::Progress(_) => "progress",
+            SimulationEvent::Disqualified { .. } => "disqualified",
+        }
+    }
+
+    /// The simulation the event is about; `None` for queue-wide events
+    pub fn simulation_id(&self) -> Option<&str> {
+        match self {
+            SimulationEvent::Queue(_) => None,
+            SimulationEvent::Status { simulation_id, .. }
+            | SimulationEvent::Progress(ProgressUpdate { simulation_id, .. })
+            | SimulationEvent::Disqualified { simulation_id, .. } => Some(simulation_id),
+        }
+    }
+
+    /// Whether the simulation has reached a status it won't leave
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            SimulationEvent::Status { status, .. }
+                if matches!(status.as_str(), "completed" | "failed" | "cancelled")
+        )
+    }
+}
+
+/// Running and queued simulations, in the order they started or will start
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueueSnapshot {
+    pub running: Vec<QueueEntry>,
+    pub queued: Vec<QueueEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueEntry {
+    pub simulation_id: String,
+    pub priority: Priority,
+    pub num_games: u32,
+    pub bot_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressUpdate {
+    pub simulation_id: String,
+    pub games_completed: u32,
+    pub num_games: u32,
+    /// Average since the simulation started or resumed
+    pub games_per_second: f64,
+    /// Estimated seconds until every game has been played
+    pub eta_seconds: Option<f64>,
+    pub standings: Vec<Standing>,
+}
+
+/// One bot's totals so far, in seat order
+#[derive(Debug, Clone, Serialize)]
+pub struct Standing {
+    pub player_index: usize,
+    pub bot_id: String,
+    pub bot_name: String,
+    pub games_won: u32,
+    pub total_money: i64,
+    pub average_money_per_game: f64,
+    pub disqualification_reason: Option<DisqualificationReason>,
+}
+
+/// Fans events out to every subscriber. Events published while nobody is
+/// subscribed are dropped.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<SimulationEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: SimulationEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SimulationEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,19 +1,11 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::Rng;
-use std::{fs, u64};
-use wasmtime::component::*;
-use wasmtime::{Config, Engine, ResourceLimiter, Store};
-use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+use rand::{Rng, SeedableRng};
 
-// Generate bindings for the WIT world
-wasmtime::component::bindgen!({
-    path: "wit",
-    world: "player",
-});
-
-// Import the GameState type from the generated bindings
-use crate::game::exports::pig_pen::player::strategy::GameState;
+use crate::recording::{BustReason, GameEvent, GameRecorder};
+use crate::rules::RuleSet;
+use crate::strategy::Strategy;
 
 #[derive(Debug, Clone)]
 pub struct PlayerState {
@@ -23,234 +15,177 @@ pub struct PlayerState {
     pub doubles_count: u32,
 }
 
-#[derive(Default)]
-pub struct StoreData {
-    pub current_memory_bytes: u64,
-    pub peak_memory_bytes: u64,
-    pub memory_limit: Option<u64>,
-    pub memory_limit_hit: bool,
-    pub wasi_ctx: WasiCtx,
-    pub resource_table: ResourceTable,
-}
-
-impl WasiView for StoreData {
-    fn ctx(&mut self) -> WasiCtxView<'_> {
-        WasiCtxView {
-            ctx: &mut self.wasi_ctx,
-            table: &mut self.resource_table,
-        }
-    }
-}
-
-impl ResourceLimiter for StoreData {
-    fn memory_growing(
-        &mut self,
-        _current: usize,
-        desired: usize,
-        maximum: Option<usize>,
-    ) -> Result<bool> {
-        let desired_bytes = desired as u64;
-
-        self.current_memory_bytes = desired_bytes;
-        if desired_bytes > self.peak_memory_bytes {
-            self.peak_memory_bytes = desired_bytes;
-        }
-
-        // Check against our limit if set
-        if let Some(limit) = self.memory_limit {
-            if desired_bytes > limit {
-                self.memory_limit_hit = true;
-                return Ok(false);
-            }
-        }
-
-        // Check against maximum if provided
-        if let Some(max) = maximum {
-            if desired > max {
-                return Ok(false);
-            }
-        }
-
-        Ok(true)
-    }
-
-    fn table_growing(
-        &mut self,
-        _current: usize,
-        _desired: usize,
-        _maximum: Option<usize>,
-    ) -> Result<bool> {
-        Ok(true)
-    }
-}
-
 // Type alias for dice roll
 pub type DiceRoll = (u32, u32);
 
 // Type alias for turn history entry (player_index, roll)
 pub type TurnHistoryEntry = (u32, DiceRoll);
 
-fn roll_dice() -> DiceRoll {
-    let mut rng = rand::rng();
-    (rng.random_range(1..=6), rng.random_range(1..=6))
+/// Game state information passed to strategies when they decide whether to roll.
+///
+/// This borrows from the running game, so strategies that don't need the
+/// history or the other players' scores never pay for copying them.
+#[derive(Debug, Clone, Copy)]
+pub struct GameState<'a> {
+    /// The current player's index in the game (0-based)
+    pub current_player_index: u32,
+    /// The player's current banked score (locked in from previous turns)
+    pub current_banked_score: u32,
+    /// The player's current total score (banked + current turn points)
+    pub current_total_score: u32,
+    /// All players' banked scores, indexed by player
+    pub all_players_banked_scores: &'a [u32],
+    /// Every roll of the game so far as (player_index, roll) pairs
+    pub turn_history: &'a [TurnHistoryEntry],
+    /// Rules the game is played under
+    pub rules: &'a RuleSet,
 }
 
-pub struct WasmStrategy {
-    store: Store<StoreData>,
-    player: Player,
+/// How a player's turn ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnOutcome {
+    /// Held (or reached the target) with `score` banked
+    Banked { score: u32 },
+    /// Busted, leaving the player on `score`
+    Bust { reason: BustReason, score: u32 },
+    /// Removed from the game for breaking a limit or crashing
+    Disqualified,
 }
 
-impl WasmStrategy {
-    pub fn new(engine: &Engine, wasm_bytes: &[u8]) -> Result<Self> {
-        let store_data = StoreData {
-            current_memory_bytes: 0,
-            peak_memory_bytes: 0,
-            memory_limit: Some(100 * 1024 * 1024), // 100MB limit per strategy
-            memory_limit_hit: false,
-            wasi_ctx: WasiCtxBuilder::new().build(),
-            resource_table: ResourceTable::new(),
-        };
-
-        let mut store = Store::new(&engine, store_data);
-        store.limiter(|tracker| tracker);
+// Type alias for the per-game outcome: (wins, money) per player, peak memory, disqualified
+pub type GameResult = (Vec<(u32, i64)>, Vec<u64>, Vec<bool>);
 
-        let component = Component::from_binary(&engine, &wasm_bytes)
-            .context("Failed to compile WASM component")?;
-
-        let mut linker = Linker::new(&engine);
-        wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
-
-        let player = Player::instantiate(&mut store, &component, &linker)
-            .context("Failed to instantiate WASM component")?;
-
-        Ok(WasmStrategy { store, player })
-    }
-
-    pub fn set_memory_limit(&mut self, limit_bytes: u64) {
-        self.store.data_mut().memory_limit = Some(limit_bytes);
-    }
-
-    pub fn from_file(engine: &Engine, wasm_path: &str) -> Result<Self> {
-        let wasm_bytes = fs::read(wasm_path)
-            .with_context(|| format!("Failed to read WASM file: {}", wasm_path))?;
-        Self::new(engine, &wasm_bytes)
-    }
-
-    fn should_roll(&mut self, state: GameState) -> Result<bool> {
-        // Check if memory limit was already hit
-        if self.store.data().memory_limit_hit {
-            return Ok(false); // Force hold if memory limit exceeded
-        }
+// With a single die the second value of the roll is always 0
+fn roll_dice(rng: &mut impl Rng, rules: &RuleSet) -> DiceRoll {
+    let die1 = rng.random_range(1..=6);
+    let die2 = if rules.num_dice >= 2 {
+        rng.random_range(1..=6)
+    } else {
+        0
+    };
+    (die1, die2)
+}
 
-        let result = self
-            .player
-            .pig_pen_player_strategy()
-            .call_should_roll(&mut self.store, &state);
-
-        // Check if the call failed due to memory limit or other WASM errors
-        match result {
-            Ok(decision) => Ok(decision),
-            Err(e) => {
-                // Check if memory limit was hit during the call
-                if self.store.data().memory_limit_hit {
-                    Ok(false) // Force hold if memory limit exceeded
-                } else {
-                    // Check if this looks like a resource/memory error
-                    let error_msg = e.to_string().to_lowercase();
-                    if error_msg.contains("memory") ||
-                       error_msg.contains("resource") ||
-                       error_msg.contains("out of") ||
-                       error_msg.contains("limit") {
-                        // Treat as memory limit exceeded
-                        self.store.data_mut().memory_limit_hit = true;
-                        Ok(false)
-                    } else {
-                        Err(e).context("Failed to call should_roll function")
-                    }
-                }
-            }
-        }
-    }
+/// Mixes a simulation seed with a stream number (splitmix64), so that every
+/// game and every bot gets its own independent, reproducible random stream.
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
 
-    pub fn is_memory_limit_exceeded(&self) -> bool {
-        self.store.data().memory_limit_hit
-    }
+/// Random number generator for the dice and seat order of one game.
+///
+/// Seeding per game (rather than once per simulation) means any single game
+/// of a run can be replayed from `(seed, game_index)` alone.
+pub fn game_rng(seed: u64, game_index: u64) -> StdRng {
+    StdRng::seed_from_u64(derive_seed(seed, game_index))
+}
 
-    pub fn peak_memory_bytes(&self) -> u64 {
-        self.store.data().peak_memory_bytes
-    }
+/// Seed for the WASI random sources handed to the bot in seat `player_index`.
+pub fn bot_seed(seed: u64, player_index: usize) -> u64 {
+    derive_seed(!seed, player_index as u64)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn simulate_turn(
     player_state: &mut PlayerState,
-    all_banked_scores: &Vec<u32>,
+    all_banked_scores: &[u32],
     player_index: usize,
-    strategy: &mut WasmStrategy,
+    strategy: &mut dyn Strategy,
     turn_history: &mut Vec<TurnHistoryEntry>,
-) -> Result<(u32, bool)> { // Return (score, memory_limit_exceeded)
+    rules: &RuleSet,
+    rng: &mut impl Rng,
+    recorder: &mut GameRecorder,
+) -> Result<TurnOutcome> {
     player_state.turn_start_score = player_state.score;
     player_state.doubles_count = 0;
     let mut must_roll = true;
     let mut _turn_points = 0u32;
 
-    loop {
+    let outcome = loop {
         // Create game state for strategy
         let game_state = GameState {
             current_player_index: player_index as u32,
             current_banked_score: player_state.banked_score,
             current_total_score: player_state.score,
-            all_players_banked_scores: all_banked_scores.clone(),
-            turn_history: turn_history.clone(),
-            // turn_history: vec![(0u32, (0u32, 0u32)); 1_000_000],
+            all_players_banked_scores: all_banked_scores,
+            turn_history,
+            rules,
         };
 
         if !must_roll {
-            let should_roll = strategy.should_roll(game_state)?;
+            let should_roll = strategy.should_roll(&game_state)?;
 
             // Check if memory limit was exceeded during the decision
-            if strategy.is_memory_limit_exceeded() {
-                return Ok((player_state.score, true)); // Return with memory limit flag
+            if strategy.is_disqualified() {
+                return Ok(TurnOutcome::Disqualified);
             }
 
+            recorder.record(|| GameEvent::Decision {
+                player: player_index,
+                roll: should_roll,
+            });
+
             if !should_roll {
                 // Player decides to hold, bank the turn points
                 player_state.banked_score = player_state.score;
-                break;
+                recorder.record(|| GameEvent::Banked {
+                    player: player_index,
+                    score: player_state.score,
+                });
+                break TurnOutcome::Banked {
+                    score: player_state.score,
+                };
             }
         }
 
-        let roll = roll_dice();
+        let roll = roll_dice(rng, rules);
         let (die1, die2) = roll;
         let sum = die1 + die2;
+        let two_dice = rules.num_dice >= 2;
 
         // Record this roll in history
         turn_history.push((player_index as u32, roll));
+        recorder.record(|| GameEvent::Roll {
+            player: player_index,
+            dice: roll,
+            forced: must_roll,
+        });
 
-        if die1 == 1 && die2 == 1 {
+        if rules.snake_eyes_busts && die1 == 1 && (!two_dice || die2 == 1) {
             // Snake eyes - score resets to 0
             player_state.score = 0;
             player_state.banked_score = 0;
             player_state.doubles_count = 0;
-            break;
+            break bust(recorder, player_index, BustReason::SnakeEyes, 0);
         }
 
-        if sum == 7 {
-            // Roll a 7 - score resets to turn start (banked score)
+        let turn_bust = if rules.seven_busts && sum == 7 {
+            Some(BustReason::Seven)
+        } else if rules.single_one_busts && (die1 == 1 || (two_dice && die2 == 1)) {
+            Some(BustReason::SingleOne)
+        } else {
+            None
+        };
+        if let Some(reason) = turn_bust {
+            // Roll a 7 (or a single 1) - score resets to turn start (banked score)
             player_state.score = player_state.turn_start_score;
             player_state.doubles_count = 0;
-            break;
+            break bust(recorder, player_index, reason, player_state.score);
         }
 
-        if die1 == die2 {
+        if two_dice && die1 == die2 {
             player_state.doubles_count += 1;
-            if player_state.doubles_count >= 3 {
+            if rules.three_doubles_busts && player_state.doubles_count >= 3 {
                 // Three doubles - score resets to 0
                 player_state.score = 0;
                 player_state.banked_score = 0;
                 player_state.doubles_count = 0;
-                break;
+                break bust(recorder, player_index, BustReason::ThreeDoubles, 0);
             }
-            must_roll = true;
+            must_roll = rules.forced_roll_on_doubles;
         } else {
             player_state.doubles_count = 0;
             must_roll = false;
@@ -259,27 +194,59 @@ pub fn simulate_turn(
         player_state.score += sum;
         _turn_points += sum;
 
-        if player_state.score == 100 {
-            // Hit exactly 100 - score resets to 0
+        if rules.exact_target_busts && player_state.score == rules.target_score {
+            // Hit the target exactly - score resets to 0
             player_state.score = 0;
             player_state.banked_score = 0;
             player_state.doubles_count = 0;
-            break;
+            break bust(recorder, player_index, BustReason::ExactTarget, 0);
         }
 
-        if player_state.score > 100 {
-            // Over 100 - bank the score if holding
+        if rules.reaches_target(player_state.score) {
+            // Reached the target - bank the score
             player_state.banked_score = player_state.score;
-            break;
+            recorder.record(|| GameEvent::Banked {
+                player: player_index,
+                score: player_state.score,
+            });
+            break TurnOutcome::Banked {
+                score: player_state.score,
+            };
         }
-    }
+    };
+
+    Ok(outcome)
+}
+
+// Records a bust and returns it as the outcome of the turn
+fn bust(recorder: &mut GameRecorder, player: usize, reason: BustReason, score: u32) -> TurnOutcome {
+    recorder.record(|| GameEvent::Bust {
+        player,
+        reason,
+        score,
+    });
+    TurnOutcome::Bust { reason, score }
+}
 
-    Ok((player_state.score, false)) // No memory limit exceeded
+// Tells every strategy at the table how a turn ended
+fn notify_turn_end(
+    strategies: &mut [Box<dyn Strategy>],
+    player: usize,
+    outcome: TurnOutcome,
+    turn_history: &[TurnHistoryEntry],
+) -> Result<()> {
+    for strategy in strategies.iter_mut() {
+        strategy.on_turn_end(player, outcome, turn_history)?;
+    }
+    Ok(())
 }
 
 pub fn simulate_game(
-    strategies: &mut Vec<WasmStrategy>,
-) -> Result<(Vec<(u32, i64)>, Vec<u64>, Vec<bool>)> {
+    strategies: &mut [Box<dyn Strategy>],
+    rules: &RuleSet,
+    rng: &mut impl Rng,
+    recorder: &mut GameRecorder,
+) -> Result<GameResult> {
     // Initial player states
     let num_players = strategies.len();
     let mut players: Vec<PlayerState> = vec![
@@ -300,8 +267,13 @@ pub fn simulate_game(
 
     // Create a randomized player order
     let mut player_order: Vec<usize> = (0..num_players).collect();
-    let mut rng = rand::rng();
-    player_order.shuffle(&mut rng);
+    player_order.shuffle(rng);
+    recorder.record(|| GameEvent::SeatOrder {
+        order: player_order.clone(),
+    });
+    for (seat, &player) in player_order.iter().enumerate() {
+        strategies[player].on_game_start(num_players, seat)?;
+    }
 
     let mut current_player_index = 0;
     let mut leader_score = 0;
@@ -319,8 +291,19 @@ pub fn simulate_game(
         }
 
         // Check for memory limit before turn
-        if strategies[current_player].is_memory_limit_exceeded() {
+        if strategies[current_player].is_disqualified() {
             disqualified[current_player] = true;
+            let reason = strategies[current_player].disqualification_reason();
+            recorder.record(|| GameEvent::Disqualified {
+                player: current_player,
+                reason,
+            });
+            notify_turn_end(
+                strategies,
+                current_player,
+                TurnOutcome::Disqualified,
+                &turn_history,
+            )?;
             // Skip to next player
             current_player_index = (current_player_index + 1) % num_players;
 
@@ -331,7 +314,6 @@ pub fn simulate_game(
                 // Early exit - declare remaining player as winner
                 if let Some(&winner_idx) = active_players.first() {
                     leader_index = winner_idx;
-                    leader_score = players[winner_idx].score;
                 }
                 break;
             }
@@ -340,17 +322,31 @@ pub fn simulate_game(
 
         let all_banked_scores: Vec<u32> = players.iter().map(|p| p.banked_score).collect();
 
-        let (_, memory_exceeded) = simulate_turn(
+        let outcome = simulate_turn(
             &mut players[current_player],
             &all_banked_scores,
             current_player,
-            &mut strategies[current_player],
+            strategies[current_player].as_mut(),
             &mut turn_history,
+            rules,
+            rng,
+            recorder,
         )?;
 
-        // Check if memory limit was exceeded during the turn
-        if memory_exceeded || strategies[current_player].is_memory_limit_exceeded() {
+        // Check if a limit was broken during the turn
+        if outcome == TurnOutcome::Disqualified || strategies[current_player].is_disqualified() {
             disqualified[current_player] = true;
+            let reason = strategies[current_player].disqualification_reason();
+            recorder.record(|| GameEvent::Disqualified {
+                player: current_player,
+                reason,
+            });
+            notify_turn_end(
+                strategies,
+                current_player,
+                TurnOutcome::Disqualified,
+                &turn_history,
+            )?;
             // Check if only one player remains
             let active_players: Vec<usize> =
                 (0..num_players).filter(|&i| !disqualified[i]).collect();
@@ -358,20 +354,24 @@ pub fn simulate_game(
                 // Early exit - declare remaining player as winner
                 if let Some(&winner_idx) = active_players.first() {
                     leader_index = winner_idx;
-                    leader_score = players[winner_idx].score;
                 }
                 break;
             }
             current_player_index = (current_player_index + 1) % num_players;
             continue;
         }
+        notify_turn_end(strategies, current_player, outcome, &turn_history)?;
 
-        if !endgame_started && players[current_player].score > 100 {
+        if !endgame_started && rules.reaches_target(players[current_player].score) {
             endgame_started = true;
             leader_score = players[current_player].score;
             leader_index = current_player;
             players_had_final_turn = vec![false; num_players];
             players_had_final_turn[current_player] = true;
+            recorder.record(|| GameEvent::EndgameTriggered {
+                player: current_player,
+                score: leader_score,
+            });
         } else if endgame_started {
             players_had_final_turn[current_player] = true;
 
@@ -381,6 +381,10 @@ pub fn simulate_game(
                 leader_index = current_player;
                 players_had_final_turn = vec![false; num_players];
                 players_had_final_turn[current_player] = true;
+                recorder.record(|| GameEvent::LeaderChanged {
+                    player: current_player,
+                    score: leader_score,
+                });
             }
 
             // Check if all active players have had their turn to catch the current leader
@@ -434,26 +438,25 @@ pub fn simulate_game(
         if i == winner_index {
             for j in 0..num_players {
                 if j != i && !disqualified[j] {
-                    let diff = winner_score - players[j].score;
-                    let payment = if players[j].score == 0 {
-                        (diff * 2) as i64
-                    } else {
-                        diff as i64
-                    };
-                    results[i].1 += payment;
+                    results[i].1 += rules.payment(winner_score, players[j].score);
                 }
             }
         } else {
-            let diff = winner_score - players[i].score;
-            let payment = if players[i].score == 0 {
-                (diff * 2) as i64
-            } else {
-                diff as i64
-            };
-            results[i].1 -= payment;
+            results[i].1 -= rules.payment(winner_score, players[i].score);
         }
     }
 
+    let final_scores: Vec<u32> = players.iter().map(|p| p.score).collect();
+    let payouts: Vec<i64> = results.iter().map(|(_, money)| *money).collect();
+    for strategy in strategies.iter_mut() {
+        strategy.on_game_end(&final_scores, &payouts, &turn_history)?;
+    }
+    recorder.record(|| GameEvent::GameEnd {
+        winner: winner_index,
+        final_scores,
+        payouts,
+    });
+
     let mut usage_stats: Vec<u64> = Vec::with_capacity(num_players);
     for strategy in strategies {
         usage_stats.push(strategy.peak_memory_bytes());
@@ -462,8 +465,41 @@ pub fn simulate_game(
     Ok((results, usage_stats, disqualified))
 }
 
-pub fn create_engine() -> Result<Engine> {
-    let mut config = Config::new();
-    config.wasm_component_model(true);
-    Ok(Engine::new(&config)?)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::builtin;
+
+    fn play(seed: u64, game_index: u64) -> (GameResult, Vec<GameEvent>) {
+        let mut strategies = vec![
+            builtin("hold-at-15").unwrap(),
+            builtin("hold-at-20").unwrap(),
+            builtin("hold-at-25").unwrap(),
+        ];
+        let mut recorder = GameRecorder::enabled();
+        let result = simulate_game(
+            &mut strategies,
+            &RuleSet::default(),
+            &mut game_rng(seed, game_index),
+            &mut recorder,
+        )
+        .unwrap();
+        (result, recorder.into_events())
+    }
+
+    #[test]
+    fn derived_seeds_are_distinct_streams() {
+        assert_eq!(derive_seed(42, 7), derive_seed(42, 7));
+        assert_ne!(derive_seed(42, 7), derive_seed(42, 8));
+        assert_ne!(derive_seed(42, 7), derive_seed(43, 7));
+        assert_ne!(bot_seed(42, 0), derive_seed(42, 0));
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_game() {
+        for game_index in 0..20 {
+            assert_eq!(play(42, game_index), play(42, game_index));
+        }
+        assert_ne!(play(42, 0).1, play(43, 0).1);
+    }
 }
@@ -0,0 +1,576 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use wasmtime::Engine;
+
+use crate::{
+    db,
+    recording::RecordingPolicy,
+    simulation::{self, GamePlan, SimulationControl, SimulationManager, SimulationSettings},
+    stats::Estimate,
+    strategy::{StrategySource, BUILTIN_PREFIX},
+    wasm,
+};
+
+/// Suite of the built-in reference bots, registered at startup
+pub const REFERENCE_SUITE_ID: &str = "builtin-reference";
+/// Games per match in the reference suite
+pub const REFERENCE_SUITE_GAMES: u32 = 100_000;
+/// Most bots a benchmark suite may hold
+pub const MAX_SUITE_BOTS: usize = 100;
+
+/// The scored bot's totals over one match; it always sits in seat 0
+#[derive(Debug, Clone, Copy)]
+pub struct MatchTotals {
+    pub games_played: u64,
+    pub games_won: i64,
+    pub total_money: i64,
+    /// Sum of squares of the bot's money per game, when it was recorded
+    pub money_squares: Option<f64>,
+}
+
+/// How a bot did against one opponent
+#[derive(Debug, Clone, Copy)]
+pub struct MatchScore {
+    pub money_per_game: f64,
+    /// 95% confidence interval for `money_per_game`, `[low, high]`
+    pub money_ci95: Option<[f64; 2]>,
+    pub win_rate: f64,
+}
+
+impl MatchTotals {
+    /// Scores a finished match; `None` if it played no games
+    pub fn score(&self) -> Option<MatchScore> {
+        if self.games_played == 0 {
+            return None;
+        }
+        let games = self.games_played as f64;
+        Some(MatchScore {
+            money_per_game: self.total_money as f64 / games,
+            money_ci95: self
+                .money_squares
+                .and_then(|squares| {
+                    Estimate::from_sums(self.games_played, self.total_money as f64, squares)
+                })
+                .map(|estimate| estimate.ci95),
+            win_rate: self.games_won as f64 / games,
+        })
+    }
+}
+
+/// Overall score of a gauntlet: money per game averaged over the opponents,
+/// each counting the same. `None` unless every match has a score.
+pub fn overall_score(scores: impl IntoIterator<Item = Option<MatchScore>>) -> Option<f64> {
+    let money: Vec<f64> = scores
+        .into_iter()
+        .map(|score| score.map(|score| score.money_per_game))
+        .collect::<Option<_>>()?;
+    (!money.is_empty()).then(|| money.iter().sum::<f64>() / money.len() as f64)
+}
+
+/// Money per game gained over the previous version, when both are known
+pub fn change(current: Option<f64>, previous: Option<f64>) -> Option<f64> {
+    current
+        .zip(previous)
+        .map(|(current, previous)| current - previous)
+}
+
+/// Settings of the match against the suite's `index`th bot, which is played
+/// with the suite's seed plus `index`
+pub fn match_settings(settings: &SimulationSettings, index: usize) -> SimulationSettings {
+    SimulationSettings {
+        seed: settings.seed.wrapping_add(index as u64),
+        ..settings.clone()
+    }
+}
+
+/// A bot's result against one member of the suite
+#[derive(Debug, Serialize)]
+pub struct OpponentScore {
+    pub bot_id: String,
+    pub bot_name: String,
+    pub simulation_id: String,
+    pub status: String,
+    pub games_played: i64,
+    /// Money the bot made per game against this opponent, once the match has
+    /// completed
+    pub money_per_game: Option<f64>,
+    /// 95% confidence interval for `money_per_game`, `[low, high]`
+    pub money_ci95: Option<[f64; 2]>,
+    pub win_rate: Option<f64>,
+    /// Money per game the previous version made against this opponent
+    pub previous_money_per_game: Option<f64>,
+    pub change: Option<f64>,
+}
+
+/// The last completed gauntlet against the same suite of an earlier upload
+/// with the same name
+#[derive(Debug, Serialize)]
+pub struct PreviousVersion {
+    pub gauntlet_id: String,
+    pub bot_id: String,
+    pub score: f64,
+    pub created_at: String,
+}
+
+/// How a bot did against every member of a benchmark suite
+#[derive(Debug, Serialize)]
+pub struct Scorecard {
+    pub gauntlet_id: String,
+    pub suite_id: String,
+    pub suite_name: String,
+    pub bot_id: String,
+    pub bot_name: String,
+    /// `running` until every match has completed, `failed` if one failed or
+    /// was cancelled
+    pub status: String,
+    pub games_per_match: u32,
+    pub opponents: Vec<OpponentScore>,
+    /// Money per game averaged over the opponents, each counting the same,
+    /// once every match has completed
+    pub score: Option<f64>,
+    pub previous: Option<PreviousVersion>,
+    pub score_change: Option<f64>,
+    pub created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct MatchRow {
+    entry_index: i64,
+    simulation_id: String,
+    status: String,
+    games_completed: i64,
+    opponent_id: String,
+    opponent_name: String,
+    games_won: i64,
+    total_money: i64,
+    money_products: Option<String>,
+}
+
+impl MatchRow {
+    /// The bot's totals, once the match has completed
+    fn totals(&self) -> Option<MatchTotals> {
+        (self.status == "completed").then(|| MatchTotals {
+            games_played: self.games_completed.max(0) as u64,
+            games_won: self.games_won,
+            total_money: self.total_money,
+            money_squares: self
+                .money_products
+                .as_deref()
+                .and_then(|json| serde_json::from_str::<Vec<f64>>(json).ok())
+                .and_then(|products| products.first().copied()),
+        })
+    }
+
+    fn score(&self) -> Option<MatchScore> {
+        self.totals()?.score()
+    }
+}
+
+/// Checks the bots of a new suite
+pub fn validate_suite(bot_ids: &[String]) -> Result<()> {
+    if bot_ids.is_empty() || bot_ids.len() > MAX_SUITE_BOTS {
+        bail!("a benchmark suite holds 1 to {} bots", MAX_SUITE_BOTS);
+    }
+    if bot_ids.iter().collect::<HashSet<_>>().len() != bot_ids.len() {
+        bail!("a bot can only be in a benchmark suite once");
+    }
+    Ok(())
+}
+
+/// Makes the built-in reference bots available as a suite. Its seed is fixed,
+/// so every gauntlet against it rolls the same dice.
+pub async fn register_reference_suite(pool: &SqlitePool) -> Result<()> {
+    let settings = SimulationSettings {
+        num_games: REFERENCE_SUITE_GAMES,
+        seed: 0,
+        rules: Default::default(),
+        recording: Default::default(),
+        isolation: Default::default(),
+        workers: simulation::default_workers(),
+        priority: Default::default(),
+        early_stopping: None,
+        fuel_per_decision: Some(wasm::DEFAULT_FUEL_PER_DECISION),
+        fuel_per_simulation: None,
+    };
+    let inserted =
+        sqlx::query("INSERT OR IGNORE INTO benchmark_suites (id, name, settings) VALUES (?, ?, ?)")
+            .bind(REFERENCE_SUITE_ID)
+            .bind("Reference bots")
+            .bind(serde_json::to_string(&settings)?)
+            .execute(pool)
+            .await?
+            .rows_affected()
+            > 0;
+    if inserted {
+        sqlx::query(
+            "INSERT INTO benchmark_suite_bots (suite_id, bot_id, entry_index)
+             SELECT ?, id, ROW_NUMBER() OVER (ORDER BY id) - 1 FROM bots WHERE file_path LIKE ?",
+        )
+        .bind(REFERENCE_SUITE_ID)
+        .bind(format!("{}%", BUILTIN_PREFIX))
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn create_suite(
+    pool: &SqlitePool,
+    name: &str,
+    bots: &[db::Bot],
+    settings: &SimulationSettings,
+) -> Result<String> {
+    let suite_id = Uuid::new_v4().to_string();
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO benchmark_suites (id, name, settings) VALUES (?, ?, ?)")
+        .bind(&suite_id)
+        .bind(name)
+        .bind(serde_json::to_string(settings)?)
+        .execute(&mut *tx)
+        .await?;
+    for (index, bot) in bots.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO benchmark_suite_bots (suite_id, bot_id, entry_index) VALUES (?, ?, ?)",
+        )
+        .bind(&suite_id)
+        .bind(&bot.id)
+        .bind(index as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(suite_id)
+}
+
+/// The bots of a suite, in entry order
+pub async fn suite_bots(pool: &SqlitePool, suite_id: &str) -> Result<Vec<db::Bot>> {
+    let bots = sqlx::query_as::<_, db::Bot>(
+        "SELECT b.* FROM benchmark_suite_bots sb
+         JOIN bots b ON b.id = sb.bot_id
+         WHERE sb.suite_id = ?
+         ORDER BY sb.entry_index",
+    )
+    .bind(suite_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(bots)
+}
+
+/// Queues a match between the bot and every bot of the suite
+pub async fn run(
+    pool: &SqlitePool,
+    manager: &SimulationManager,
+    suite: &db::BenchmarkSuite,
+    bot: &db::Bot,
+) -> Result<String> {
+    let settings: SimulationSettings = serde_json::from_str(&suite.settings)?;
+    let opponents = suite_bots(pool, &suite.id).await?;
+
+    let gauntlet_id = Uuid::new_v4().to_string();
+    println!(
+        "[GAUNTLET {}] {} against {} bots of {}",
+        gauntlet_id,
+        bot.name,
+        opponents.len(),
+        suite.name
+    );
+
+    // Every match is queued before the gauntlet is stored, so one that fails
+    // to queue never leaves a gauntlet with some of its matches missing
+    let mut simulation_ids = Vec::new();
+    for (index, opponent) in opponents.into_iter().enumerate() {
+        let simulation_id = manager
+            .create(
+                vec![bot.clone(), opponent],
+                match_settings(&settings, index),
+            )
+            .await?;
+        simulation_ids.push(simulation_id);
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO gauntlets (id, suite_id, bot_id) VALUES (?, ?, ?)")
+        .bind(&gauntlet_id)
+        .bind(&suite.id)
+        .bind(&bot.id)
+        .execute(&mut *tx)
+        .await?;
+    for (index, simulation_id) in simulation_ids.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO gauntlet_matches (gauntlet_id, entry_index, simulation_id) VALUES (?, ?, ?)",
+        )
+        .bind(&gauntlet_id)
+        .bind(index as i64)
+        .bind(simulation_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(gauntlet_id)
+}
+
+/// The bot's seat in each match of a gauntlet
+async fn matches(pool: &SqlitePool, gauntlet_id: &str) -> Result<Vec<MatchRow>> {
+    let rows = sqlx::query_as::<_, MatchRow>(
+        "SELECT gm.entry_index, gm.simulation_id, s.status, s.games_completed,
+                sb.bot_id AS opponent_id, b.name AS opponent_name,
+                sp.games_won, sp.total_money, sp.money_products
+         FROM gauntlet_matches gm
+         JOIN gauntlets g ON g.id = gm.gauntlet_id
+         JOIN simulations s ON s.id = gm.simulation_id
+         JOIN benchmark_suite_bots sb ON sb.suite_id = g.suite_id AND sb.entry_index = gm.entry_index
+         JOIN bots b ON b.id = sb.bot_id
+         JOIN simulation_participants sp ON sp.simulation_id = gm.simulation_id AND sp.player_index = 0
+         WHERE gm.gauntlet_id = ?
+         ORDER BY gm.entry_index",
+    )
+    .bind(gauntlet_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+fn status(matches: &[MatchRow]) -> &'static str {
+    if matches
+        .iter()
+        .any(|m| matches!(m.status.as_str(), "failed" | "cancelled"))
+    {
+        "failed"
+    } else if matches.iter().all(|m| m.status == "completed") {
+        "completed"
+    } else {
+        "running"
+    }
+}
+
+/// Overall score of the matches, if they have all completed
+fn mean_score(matches: &[MatchRow]) -> Option<f64> {
+    overall_score(matches.iter().map(MatchRow::score))
+}
+
+/// Finds the last completed gauntlet against the same suite of an earlier
+/// upload of the bot, going by name
+async fn previous_version(
+    pool: &SqlitePool,
+    gauntlet: &db::Gauntlet,
+) -> Result<Option<(db::Gauntlet, Vec<MatchRow>)>> {
+    // Bots are compared by rowid, which follows upload order even within the
+    // same second
+    let candidates = sqlx::query_as::<_, db::Gauntlet>(
+        "SELECT g.* FROM gauntlets g
+         JOIN bots b ON b.id = g.bot_id
+         JOIN bots current ON current.id = ?
+         WHERE g.suite_id = ? AND b.name = current.name AND b.rowid < current.rowid
+         ORDER BY b.rowid DESC, g.created_at DESC",
+    )
+    .bind(&gauntlet.bot_id)
+    .bind(&gauntlet.suite_id)
+    .fetch_all(pool)
+    .await?;
+
+    for candidate in candidates {
+        let rows = matches(pool, &candidate.id).await?;
+        if !rows.is_empty() && status(&rows) == "completed" {
+            return Ok(Some((candidate, rows)));
+        }
+    }
+    Ok(None)
+}
+
+pub async fn scorecard(pool: &SqlitePool, gauntlet: db::Gauntlet) -> Result<Scorecard> {
+    let suite =
+        sqlx::query_as::<_, db::BenchmarkSuite>("SELECT * FROM benchmark_suites WHERE id = ?")
+            .bind(&gauntlet.suite_id)
+            .fetch_one(pool)
+            .await?;
+    let settings: SimulationSettings = serde_json::from_str(&suite.settings)?;
+    let bot_name: String = sqlx::query_scalar("SELECT name FROM bots WHERE id = ?")
+        .bind(&gauntlet.bot_id)
+        .fetch_one(pool)
+        .await?;
+
+    let rows = matches(pool, &gauntlet.id).await?;
+    let score = mean_score(&rows);
+    let previous = previous_version(pool, &gauntlet).await?;
+    let previous_money: HashMap<i64, f64> = previous
+        .iter()
+        .flat_map(|(_, rows)| rows)
+        .filter_map(|row| Some((row.entry_index, row.score()?.money_per_game)))
+        .collect();
+
+    let opponents = rows
+        .iter()
+        .map(|row| {
+            let score = row.score();
+            let money_per_game = score.map(|score| score.money_per_game);
+            let previous_money_per_game = previous_money.get(&row.entry_index).copied();
+            OpponentScore {
+                bot_id: row.opponent_id.clone(),
+                bot_name: row.opponent_name.clone(),
+                simulation_id: row.simulation_id.clone(),
+                status: row.status.clone(),
+                games_played: row.games_completed,
+                money_per_game,
+                money_ci95: score.and_then(|score| score.money_ci95),
+                win_rate: score.map(|score| score.win_rate),
+                previous_money_per_game,
+                change: change(money_per_game, previous_money_per_game),
+            }
+        })
+        .collect();
+
+    let previous = previous.and_then(|(previous, rows)| {
+        Some(PreviousVersion {
+            gauntlet_id: previous.id,
+            bot_id: previous.bot_id,
+            score: mean_score(&rows)?,
+            created_at: previous.created_at,
+        })
+    });
+    let score_change = change(score, previous.as_ref().map(|previous| previous.score));
+
+    Ok(Scorecard {
+        gauntlet_id: gauntlet.id,
+        suite_id: suite.id,
+        suite_name: suite.name,
+        bot_id: gauntlet.bot_id,
+        bot_name,
+        status: status(&rows).to_string(),
+        games_per_match: settings.num_games,
+        opponents,
+        score,
+        previous,
+        score_change,
+        created_at: gauntlet.created_at,
+    })
+}
+
+/// One row of a gauntlet played by `run_local`
+pub struct LocalMatch {
+    pub opponent: String,
+    pub score: Option<MatchScore>,
+    /// The previous version's result against the same opponent, if one was given
+    pub previous: Option<MatchScore>,
+}
+
+impl LocalMatch {
+    pub fn change(&self) -> Option<f64> {
+        change(
+            self.score.map(|score| score.money_per_game),
+            self.previous.map(|score| score.money_per_game),
+        )
+    }
+}
+
+/// Plays a gauntlet from strategies loaded outside the database, as the
+/// command line does. Each match gets the settings a stored suite with
+/// `settings` would give it, and the previous version, if any, is played
+/// against every opponent with the same seeds.
+pub fn run_local(
+    engine: &Engine,
+    settings: &SimulationSettings,
+    bot: &(StrategySource, String),
+    previous: Option<&(StrategySource, String)>,
+    suite: &[(StrategySource, String)],
+) -> Result<Vec<LocalMatch>> {
+    let mut matches = Vec::new();
+    for (index, opponent) in suite.iter().enumerate() {
+        let settings = match_settings(settings, index);
+        let score = play_local_match(engine, &settings, bot, opponent)?.score();
+        let previous = previous
+            .map(|previous| play_local_match(engine, &settings, previous, opponent))
+            .transpose()?
+            .and_then(|totals| totals.score());
+        matches.push(LocalMatch {
+            opponent: opponent.1.clone(),
+            score,
+            previous,
+        });
+    }
+    Ok(matches)
+}
+
+/// Plays `bot` against `opponent` and returns the bot's totals
+fn play_local_match(
+    engine: &Engine,
+    settings: &SimulationSettings,
+    bot: &(StrategySource, String),
+    opponent: &(StrategySource, String),
+) -> Result<MatchTotals> {
+    println!("Playing {} vs {}", bot.1, opponent.1);
+    let sources = [bot.0.clone(), opponent.0.clone()];
+    let names = [bot.1.clone(), opponent.1.clone()];
+    let plan = GamePlan {
+        engine,
+        sources: &sources,
+        names: &names,
+        num_games: settings.num_games,
+        seed: settings.seed,
+        rules: &settings.rules,
+        isolation: settings.isolation,
+        recording: RecordingPolicy::None,
+        memory_limit: None,
+        fuel_per_decision: settings.fuel_per_decision,
+        fuel_per_simulation: settings.fuel_per_simulation,
+        early_stopping: settings.early_stopping,
+        resumed: None,
+        shards: simulation::shard_games(settings.num_games, settings.workers),
+    };
+    let outcome = plan.play(&SimulationControl::default(), |_| Ok(()), |_, _| {})?;
+    let total = &outcome.totals[0];
+    Ok(MatchTotals {
+        games_played: outcome.games_played as u64,
+        games_won: total.games_won as i64,
+        total_money: total.total_money,
+        money_squares: total.money_products.first().copied(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals(games_played: u64, games_won: i64, total_money: i64) -> MatchTotals {
+        MatchTotals {
+            games_played,
+            games_won,
+            total_money,
+            money_squares: None,
+        }
+    }
+
+    #[test]
+    fn match_score_is_per_game() {
+        let score = MatchTotals {
+            money_squares: Some(30.0),
+            ..totals(4, 3, 10)
+        }
+        .score()
+        .unwrap();
+        assert_eq!(score.money_per_game, 2.5);
+        assert_eq!(score.win_rate, 0.75);
+        let [low, high] = score.money_ci95.unwrap();
+        assert!((low - 1.234_849).abs() < 1e-6 && (high - 3.765_151).abs() < 1e-6);
+
+        assert!(totals(0, 0, 0).score().is_none());
+        assert!(totals(4, 3, 10).score().unwrap().money_ci95.is_none());
+    }
+
+    #[test]
+    fn overall_score_needs_every_match() {
+        let scores = [totals(10, 5, 20).score(), totals(10, 5, -40).score()];
+        assert_eq!(overall_score(scores), Some(-1.0));
+        assert_eq!(overall_score([scores[0], None]), None);
+        assert_eq!(overall_score([]), None);
+    }
+
+    #[test]
+    fn change_needs_both_versions() {
+        assert_eq!(change(Some(3.0), Some(1.0)), Some(2.0));
+        assert_eq!(change(Some(3.0), None), None);
+        assert_eq!(change(None, Some(1.0)), None);
+    }
+}
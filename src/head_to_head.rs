@@ -0,0 +1,255 @@
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::rules::RuleSet;
+
+/// Which completed simulations the matrix is built from
+#[derive(Debug, Default)]
+pub struct MatrixFilter {
+    /// Earliest completion time, inclusive, as normalized by `parse_time`
+    pub from: Option<String>,
+    /// Latest completion time, exclusive
+    pub to: Option<String>,
+    /// Only simulations played under exactly these rules
+    pub rules: Option<RuleSet>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatrixBot {
+    pub bot_id: String,
+    pub bot_name: String,
+}
+
+/// How a bot did in the simulations it played with one opponent at the table
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct HeadToHeadCell {
+    pub simulations: u32,
+    pub games: u64,
+    pub money_per_game: f64,
+    pub win_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeadToHeadMatrix {
+    pub bots: Vec<MatrixBot>,
+    /// `matrix[i][j]` is how `bots[i]` did against `bots[j]`; `None` when
+    /// they never met
+    pub matrix: Vec<Vec<Option<HeadToHeadCell>>>,
+    /// Completed simulations that matched the filter and had at least two
+    /// different bots
+    pub simulations: u32,
+}
+
+#[derive(sqlx::FromRow)]
+struct Seat {
+    simulation_id: String,
+    rules: Option<String>,
+    games_completed: i64,
+    bot_id: String,
+    bot_name: String,
+    games_won: i64,
+    total_money: i64,
+}
+
+/// Running sums behind a cell
+#[derive(Default)]
+struct Totals {
+    simulations: u32,
+    games: u64,
+    money: f64,
+    wins: f64,
+}
+
+/// Turns a date (`2024-05-01`) or date-time (`2024-05-01 12:00:00`, UTC) into
+/// the form completion times are stored in. `None` if SQLite can't read it.
+pub async fn parse_time(pool: &SqlitePool, time: &str) -> Result<Option<String>> {
+    let parsed = sqlx::query_scalar("SELECT datetime(?)")
+        .bind(time)
+        .fetch_one(pool)
+        .await?;
+    Ok(parsed)
+}
+
+/// Builds the bot-vs-bot matrix from every completed simulation that matches
+/// the filter. Each pair of bots that sat at the same table counts the games
+/// of that simulation; at larger tables a bot's money and wins include what
+/// it took from the other players too. A bot in several seats counts the
+/// average of its seats.
+pub async fn matrix(pool: &SqlitePool, filter: &MatrixFilter) -> Result<HeadToHeadMatrix> {
+    let seats = sqlx::query_as::<_, Seat>(
+        "SELECT s.id AS simulation_id, s.rules, s.games_completed,
+                sp.bot_id, b.name AS bot_name, sp.games_won, sp.total_money
+         FROM simulations s
+         JOIN simulation_participants sp ON sp.simulation_id = s.id
+         JOIN bots b ON b.id = sp.bot_id
+         WHERE s.status = 'completed' AND s.games_completed > 0
+           AND (? IS NULL OR s.completed_at >= ?)
+           AND (? IS NULL OR s.completed_at < ?)
+         ORDER BY s.id, sp.player_index",
+    )
+    .bind(&filter.from)
+    .bind(&filter.from)
+    .bind(&filter.to)
+    .bind(&filter.to)
+    .fetch_all(pool)
+    .await?;
+
+    let mut names = HashMap::new();
+    let mut totals: HashMap<(String, String), Totals> = HashMap::new();
+    let mut simulations = 0;
+    for table in seats.chunk_by(|a, b| a.simulation_id == b.simulation_id) {
+        if let Some(rules) = &filter.rules {
+            // Simulations from before rule sets were stored played classic
+            // rules, and rules that can't be read match no filter
+            let played: Option<RuleSet> = match table[0].rules.as_deref() {
+                Some(json) => serde_json::from_str(json).ok(),
+                None => Some(RuleSet::default()),
+            };
+            if played.as_ref() != Some(rules) {
+                continue;
+            }
+        }
+
+        if !add_table(&mut totals, table) {
+            continue;
+        }
+        simulations += 1;
+        for seat in table {
+            names
+                .entry(seat.bot_id.clone())
+                .or_insert_with(|| seat.bot_name.clone());
+        }
+    }
+
+    // Bots are listed by name, then id
+    let mut bots: Vec<MatrixBot> = names
+        .into_iter()
+        .map(|(bot_id, bot_name)| MatrixBot { bot_id, bot_name })
+        .collect();
+    bots.sort_by(|a, b| (&a.bot_name, &a.bot_id).cmp(&(&b.bot_name, &b.bot_id)));
+
+    let matrix = bots
+        .iter()
+        .map(|bot| {
+            bots.iter()
+                .map(|opponent| {
+                    let totals = totals.get(&(bot.bot_id.clone(), opponent.bot_id.clone()))?;
+                    Some(HeadToHeadCell {
+                        simulations: totals.simulations,
+                        games: totals.games,
+                        money_per_game: totals.money / totals.games as f64,
+                        win_rate: totals.wins / totals.games as f64,
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(HeadToHeadMatrix {
+        bots,
+        matrix,
+        simulations,
+    })
+}
+
+/// Adds the seats of one simulation to the totals of every pair of bots at
+/// the table. Returns `false`, adding nothing, when fewer than two different
+/// bots played.
+fn add_table(totals: &mut HashMap<(String, String), Totals>, table: &[Seat]) -> bool {
+    // Money and wins per seat of each distinct bot
+    let mut bots: Vec<(&str, f64, f64, u32)> = Vec::new();
+    for seat in table {
+        match bots.iter_mut().find(|(bot_id, ..)| *bot_id == seat.bot_id) {
+            Some((_, money, wins, count)) => {
+                *money += seat.total_money as f64;
+                *wins += seat.games_won as f64;
+                *count += 1;
+            }
+            None => bots.push((
+                &seat.bot_id,
+                seat.total_money as f64,
+                seat.games_won as f64,
+                1,
+            )),
+        }
+    }
+    if bots.len() < 2 {
+        return false;
+    }
+
+    let games = table[0].games_completed as u64;
+    for &(bot_id, money, wins, count) in &bots {
+        for &(opponent_id, ..) in &bots {
+            if bot_id == opponent_id {
+                continue;
+            }
+            let cell = totals
+                .entry((bot_id.to_string(), opponent_id.to_string()))
+                .or_default();
+            cell.simulations += 1;
+            cell.games += games;
+            cell.money += money / count as f64;
+            cell.wins += wins / count as f64;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seat(simulation_id: &str, bot_id: &str, games_won: i64, total_money: i64) -> Seat {
+        Seat {
+            simulation_id: simulation_id.to_string(),
+            rules: None,
+            games_completed: 10,
+            bot_id: bot_id.to_string(),
+            bot_name: bot_id.to_string(),
+            games_won,
+            total_money,
+        }
+    }
+
+    fn cell<'a>(
+        totals: &'a HashMap<(String, String), Totals>,
+        bot: &str,
+        opponent: &str,
+    ) -> &'a Totals {
+        &totals[&(bot.to_string(), opponent.to_string())]
+    }
+
+    #[test]
+    fn bot_in_several_seats_counts_their_average() {
+        let mut totals = HashMap::new();
+        let table = [
+            seat("s1", "a", 3, 10),
+            seat("s1", "b", 4, -30),
+            seat("s1", "a", 3, 20),
+        ];
+        assert!(add_table(&mut totals, &table));
+        assert!(add_table(
+            &mut totals,
+            &[seat("s2", "a", 6, -5), seat("s2", "b", 4, 5)]
+        ));
+
+        let a = cell(&totals, "a", "b");
+        assert_eq!((a.simulations, a.games), (2, 20));
+        assert_eq!((a.money, a.wins), (10.0, 9.0));
+        let b = cell(&totals, "b", "a");
+        assert_eq!((b.simulations, b.games), (2, 20));
+        assert_eq!((b.money, b.wins), (-25.0, 8.0));
+    }
+
+    #[test]
+    fn table_of_one_bot_is_not_counted() {
+        let mut totals = HashMap::new();
+        assert!(!add_table(
+            &mut totals,
+            &[seat("s1", "a", 5, 10), seat("s1", "a", 5, -10)]
+        ));
+        assert!(totals.is_empty());
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{broadcast::error::RecvError, watch, Mutex};
+
+use crate::{
+    db,
+    events::SimulationEvent,
+    ratings::Rating,
+    simulation::{self, Priority, SimulationManager, SimulationSettings},
+    strategy::BUILTIN_PREFIX,
+    wasm,
+};
+
+/// How often the ladder checks for an idle queue, in case it missed the event
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy)]
+pub struct LadderConfig {
+    pub games_per_match: u32,
+    /// Matches a bot plays against a sample of the ladder when it enters
+    pub placement_matches: usize,
+}
+
+/// A bot's place on the ladder
+#[derive(Debug, Clone, Serialize)]
+pub struct LadderStanding {
+    pub rank: usize,
+    pub bot_id: String,
+    pub bot_name: String,
+    pub mu: f64,
+    pub sigma: f64,
+    /// `mu - 3 sigma`, what the ladder is ordered by
+    pub rating: f64,
+    pub matches_played: u32,
+    /// Matches it made more money in, or won more games when the money was level
+    pub matches_won: u32,
+    /// Placement matches still queued or running; the rating is provisional
+    /// until they are done
+    pub placement_matches_left: u32,
+    pub joined_at: String,
+}
+
+/// Keeps a ladder of bots playing two-bot matches: placement matches when a
+/// bot enters, and background matches whenever the simulation queue is idle.
+/// Bots are ranked by their rating.
+pub struct Ladder {
+    pool: SqlitePool,
+    manager: Arc<SimulationManager>,
+    config: LadderConfig,
+    leaderboard: watch::Sender<Vec<LadderStanding>>,
+    /// Entering bots and picking background matches both read the ladder
+    /// before adding to it, so they run one at a time
+    schedule_lock: Mutex<()>,
+}
+
+#[derive(sqlx::FromRow)]
+struct Entrant {
+    bot_id: String,
+    bot_name: String,
+    joined_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct LadderSeat {
+    simulation_id: String,
+    kind: String,
+    placed_bot_id: Option<String>,
+    status: String,
+    bot_id: String,
+    games_won: i64,
+    total_money: i64,
+}
+
+impl Ladder {
+    /// Enters the built-in bots, which anchor the ratings, and starts
+    /// scheduling matches
+    pub async fn start(
+        pool: SqlitePool,
+        manager: Arc<SimulationManager>,
+        config: LadderConfig,
+    ) -> Result<Arc<Ladder>> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO ladder_entrants (bot_id)
+             SELECT id FROM bots WHERE file_path LIKE ?",
+        )
+        .bind(format!("{}%", BUILTIN_PREFIX))
+        .execute(&pool)
+        .await?;
+
+        let (leaderboard, _) = watch::channel(Vec::new());
+        let ladder = Arc::new(Ladder {
+            pool,
+            manager,
+            config,
+            leaderboard,
+            schedule_lock: Mutex::new(()),
+        });
+        ladder.refresh().await?;
+        println!(
+            "[LADDER] Started with {} bots",
+            ladder.leaderboard.borrow().len()
+        );
+        tokio::spawn(ladder.clone().run());
+        Ok(ladder)
+    }
+
+    /// The leaderboard as of the last finished ladder match
+    pub fn leaderboard(&self) -> Vec<LadderStanding> {
+        self.leaderboard.borrow().clone()
+    }
+
+    /// Sees every new leaderboard
+    pub fn subscribe(&self) -> watch::Receiver<Vec<LadderStanding>> {
+        self.leaderboard.subscribe()
+    }
+
+    /// Puts a bot on the ladder and queues its placement matches. An earlier
+    /// upload with the same name leaves the ladder, so it shows the latest
+    /// version of every bot.
+    pub async fn enter(&self, bot: &db::Bot) -> Result<()> {
+        let _guard = self.schedule_lock.lock().await;
+
+        sqlx::query(
+            "INSERT INTO ladder_entrants (bot_id) VALUES (?)
+             ON CONFLICT(bot_id) DO UPDATE SET retired_at = NULL, joined_at = CURRENT_TIMESTAMP",
+        )
+        .bind(&bot.id)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "UPDATE ladder_entrants SET retired_at = CURRENT_TIMESTAMP
+             WHERE retired_at IS NULL AND bot_id != ?
+               AND bot_id IN (SELECT id FROM bots WHERE name = ?)",
+        )
+        .bind(&bot.id)
+        .bind(&bot.name)
+        .execute(&self.pool)
+        .await?;
+
+        let mut opponents = sqlx::query_as::<_, db::Bot>(
+            "SELECT b.* FROM ladder_entrants le
+             JOIN bots b ON b.id = le.bot_id
+             WHERE le.retired_at IS NULL AND le.bot_id != ?",
+        )
+        .bind(&bot.id)
+        .fetch_all(&self.pool)
+        .await?;
+        opponents.shuffle(&mut rand::rng());
+        opponents.truncate(self.config.placement_matches);
+
+        println!(
+            "[LADDER] {} entered, queueing {} placement matches",
+            bot.name,
+            opponents.len()
+        );
+        for opponent in opponents {
+            self.queue_match([bot.clone(), opponent], "placement", Priority::Normal)
+                .await?;
+        }
+        self.refresh().await
+    }
+
+    /// Takes a bot off the ladder. Returns `false` if it wasn't on it.
+    pub async fn withdraw(&self, bot_id: &str) -> Result<bool> {
+        let withdrawn = sqlx::query(
+            "UPDATE ladder_entrants SET retired_at = CURRENT_TIMESTAMP
+             WHERE bot_id = ? AND retired_at IS NULL",
+        )
+        .bind(bot_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+        if withdrawn {
+            self.refresh().await?;
+        }
+        Ok(withdrawn)
+    }
+
+    async fn queue_match(&self, bots: [db::Bot; 2], kind: &str, priority: Priority) -> Result<()> {
+        let placed_bot_id = bots[0].id.clone();
+        let settings = SimulationSettings {
+            num_games: self.config.games_per_match,
+            seed: simulation::random_seed(),
+            rules: Default::default(),
+            recording: Default::default(),
+            isolation: Default::default(),
+            workers: simulation::default_workers(),
+            priority,
+            early_stopping: None,
+            fuel_per_decision: Some(wasm::DEFAULT_FUEL_PER_DECISION),
+            fuel_per_simulation: None,
+        };
+        let simulation_id = self.manager.create(bots.to_vec(), settings).await?;
+        sqlx::query("INSERT INTO ladder_matches (simulation_id, kind, bot_id) VALUES (?, ?, ?)")
+            .bind(&simulation_id)
+            .bind(kind)
+            .bind((kind == "placement").then_some(placed_bot_id))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn run(self: Arc<Self>) {
+        let mut events = self.manager.subscribe();
+        let mut idle_check = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        loop {
+            let result = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(SimulationEvent::Queue(queue)) => {
+                        if queue.running.is_empty() && queue.queued.is_empty() {
+                            self.play_background_match().await
+                        } else {
+                            Ok(())
+                        }
+                    }
+                    Ok(event) if event.is_final() => match event.simulation_id() {
+                        Some(simulation_id) => self.match_finished(simulation_id).await,
+                        None => Ok(()),
+                    },
+                    Ok(_) => Ok(()),
+                    Err(RecvError::Lagged(_)) => self.refresh().await,
+                    Err(RecvError::Closed) => return,
+                },
+                _ = idle_check.tick() => {
+                    let queue = self.manager.queue_snapshot();
+                    if queue.running.is_empty() && queue.queued.is_empty() {
+                        self.play_background_match().await
+                    } else {
+                        Ok(())
+                    }
+                }
+            };
+            if let Err(e) = result {
+                println!("[LADDER] {}", e);
+            }
+        }
+    }
+
+    async fn match_finished(&self, simulation_id: &str) -> Result<()> {
+        // Waits for a match that finished while it was being queued to be recorded
+        let _guard = self.schedule_lock.lock().await;
+        let is_ladder_match: bool =
+            sqlx::query_scalar("SELECT COUNT(*) > 0 FROM ladder_matches WHERE simulation_id = ?")
+                .bind(simulation_id)
+                .fetch_one(&self.pool)
+                .await?;
+        if is_ladder_match {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// Queues a match for the bot that has played the fewest ladder matches,
+    /// against the bot rated closest to it
+    async fn play_background_match(&self) -> Result<()> {
+        let _guard = self.schedule_lock.lock().await;
+        let mut entrants = self.leaderboard();
+        if entrants.len() < 2 {
+            return Ok(());
+        }
+
+        let matches: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT sp.bot_id, COUNT(*) FROM ladder_matches lm
+             JOIN simulation_participants sp ON sp.simulation_id = lm.simulation_id
+             GROUP BY sp.bot_id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let matches: HashMap<String, i64> = matches.into_iter().collect();
+
+        entrants.shuffle(&mut rand::rng());
+        let (index, _) = entrants
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entrant)| matches.get(&entrant.bot_id).copied().unwrap_or(0))
+            .expect("ladder has entrants");
+        let first = entrants.swap_remove(index);
+        let second = entrants
+            .into_iter()
+            .min_by(|a, b| {
+                (a.rating - first.rating)
+                    .abs()
+                    .total_cmp(&(b.rating - first.rating).abs())
+            })
+            .expect("ladder has another entrant");
+
+        let mut bots = Vec::new();
+        for bot_id in [&first.bot_id, &second.bot_id] {
+            bots.push(
+                sqlx::query_as::<_, db::Bot>("SELECT * FROM bots WHERE id = ?")
+                    .bind(bot_id)
+                    .fetch_one(&self.pool)
+                    .await?,
+            );
+        }
+        let [a, b]: [db::Bot; 2] = bots.try_into().expect("two bots");
+        println!("[LADDER] Queue is idle, queueing {} vs {}", a.name, b.name);
+        self.queue_match([a, b], "background", Priority::Low).await
+    }
+
+    /// Recomputes the leaderboard and sends it to subscribers
+    async fn refresh(&self) -> Result<()> {
+        let entrants = sqlx::query_as::<_, Entrant>(
+            "SELECT le.bot_id, b.name AS bot_name, le.joined_at FROM ladder_entrants le
+             JOIN bots b ON b.id = le.bot_id
+             WHERE le.retired_at IS NULL
+             ORDER BY le.joined_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let ratings: Vec<(String, f64, f64)> =
+            sqlx::query_as("SELECT bot_id, mu, sigma FROM bot_ratings")
+                .fetch_all(&self.pool)
+                .await?;
+        let ratings: HashMap<String, Rating> = ratings
+            .into_iter()
+            .map(|(bot_id, mu, sigma)| (bot_id, Rating { mu, sigma }))
+            .collect();
+        let seats = sqlx::query_as::<_, LadderSeat>(
+            "SELECT lm.simulation_id, lm.kind, lm.bot_id AS placed_bot_id, s.status,
+                    sp.bot_id, sp.games_won, sp.total_money
+             FROM ladder_matches lm
+             JOIN simulations s ON s.id = lm.simulation_id
+             JOIN simulation_participants sp ON sp.simulation_id = lm.simulation_id
+             ORDER BY lm.simulation_id, sp.player_index",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut leaderboard: Vec<LadderStanding> = entrants
+            .into_iter()
+            .map(|entrant| {
+                let rating = ratings.get(&entrant.bot_id).copied().unwrap_or_default();
+                LadderStanding {
+                    rank: 0,
+                    bot_id: entrant.bot_id,
+                    bot_name: entrant.bot_name,
+                    mu: rating.mu,
+                    sigma: rating.sigma,
+                    rating: rating.conservative(),
+                    matches_played: 0,
+                    matches_won: 0,
+                    placement_matches_left: 0,
+                    joined_at: entrant.joined_at,
+                }
+            })
+            .collect();
+        let positions: HashMap<String, usize> = leaderboard
+            .iter()
+            .enumerate()
+            .map(|(i, standing)| (standing.bot_id.clone(), i))
+            .collect();
+
+        for table in seats.chunk_by(|a, b| a.simulation_id == b.simulation_id) {
+            let status = table[0].status.as_str();
+            if table[0].kind == "placement" && matches!(status, "pending" | "running" | "paused") {
+                if let Some(&position) = table[0]
+                    .placed_bot_id
+                    .as_ref()
+                    .and_then(|bot_id| positions.get(bot_id))
+                {
+                    leaderboard[position].placement_matches_left += 1;
+                }
+            }
+            if status != "completed" {
+                continue;
+            }
+            for seat in table {
+                let Some(&position) = positions.get(&seat.bot_id) else {
+                    continue;
+                };
+                leaderboard[position].matches_played += 1;
+                let won = table.iter().all(|other| {
+                    other.bot_id == seat.bot_id
+                        || (seat.total_money, seat.games_won) > (other.total_money, other.games_won)
+                });
+                if won {
+                    leaderboard[position].matches_won += 1;
+                }
+            }
+        }
+
+        leaderboard.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        for (i, standing) in leaderboard.iter_mut().enumerate() {
+            standing.rank = i + 1;
+        }
+        self.leaderboard.send_replace(leaderboard);
+        Ok(())
+    }
+}
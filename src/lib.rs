@@ -1,4 +1,16 @@
 pub mod api;
+pub mod cache;
 pub mod db;
+pub mod events;
 pub mod game;
+pub mod gauntlet;
+pub mod head_to_head;
+pub mod ladder;
+pub mod ratings;
+pub mod recording;
+pub mod rules;
 pub mod simulation;
+pub mod stats;
+pub mod strategy;
+pub mod tournament;
+pub mod wasm;
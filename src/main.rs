@@ -2,9 +2,23 @@ use anyhow::Result;
 use axum::http::{header, HeaderValue};
 use axum::Router;
 use clap::{Parser, Subcommand};
-use pig_pen::{api, db, game, simulation::SimulationManager};
+use pig_pen::{
+    api,
+    cache::ComponentCache,
+    db, gauntlet,
+    ladder::{Ladder, LadderConfig},
+    ratings,
+    recording::RecordingPolicy,
+    rules::RuleSet,
+    simulation::{
+        self, GamePlan, InterruptedPolicy, SimulationControl, SimulationManager, SimulationSettings,
+    },
+    stats::{self, EarlyStopping, Estimate},
+    strategy::{Isolation, StrategySource, BUILTIN_PREFIX, REFERENCE_BOTS},
+    tournament, wasm,
+};
 use std::{path::PathBuf, sync::Arc};
-use tokio::{fs, net::TcpListener, sync::RwLock};
+use tokio::{fs, net::TcpListener};
 use tower_http::{
     cors::CorsLayer,
     services::{ServeDir, ServeFile},
@@ -21,19 +35,100 @@ struct Cli {
     /// Port to run the web server on
     #[arg(short, long, default_value = "8080")]
     port: u16,
+
+    /// Simulations the server runs at the same time
+    #[arg(long, default_value = "2")]
+    max_concurrent_simulations: usize,
+
+    /// What to do at startup with simulations a previous run left unfinished: restart or fail
+    #[arg(long, default_value = "restart")]
+    interrupted_simulations: InterruptedPolicy,
+
+    /// Run the ladder: placement matches for new bots and background matches while the queue is idle
+    #[arg(long)]
+    ladder: bool,
+
+    /// Games in each ladder match
+    #[arg(long, default_value = "10000")]
+    ladder_games: u32,
+
+    /// Matches a bot plays against a sample of the ladder when it enters
+    #[arg(long, default_value = "5")]
+    ladder_placement_matches: usize,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Run simulation with WASM strategies
     Simulate {
-        /// WASM strategy files to load
+        /// WASM strategy files to load, or native strategies such as builtin:hold-at-20
         #[arg(required = true)]
         strategies: Vec<PathBuf>,
 
         /// Number of games to simulate
         #[arg(short = 'n', long, default_value = "1000000")]
         games: usize,
+
+        /// Seed for reproducible runs (random if omitted)
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        /// Rule preset (classic, one-die, no-exact-bust) or path to a JSON rule file
+        #[arg(short, long, default_value = "classic")]
+        rules: String,
+
+        /// CPU budget per decision, in wasmtime fuel units
+        #[arg(long, default_value_t = wasm::DEFAULT_FUEL_PER_DECISION)]
+        fuel_per_decision: u64,
+
+        /// CPU budget per bot for the whole run, in wasmtime fuel units
+        #[arg(long)]
+        fuel_per_simulation: Option<u64>,
+
+        /// When bots start over from a fresh instance: persistent, per-game or every:<games>
+        #[arg(long, default_value = "persistent")]
+        isolation: Isolation,
+
+        /// Worker threads to split the games across (one per CPU core if omitted)
+        #[arg(short, long)]
+        workers: Option<usize>,
+
+        /// Stop once the results are conclusive: ci-width:<dollars> or sprt:<dollars>
+        #[arg(long)]
+        early_stopping: Option<EarlyStopping>,
+    },
+    /// Score a strategy against a benchmark suite, one match per opponent
+    Gauntlet {
+        /// WASM strategy file to score
+        strategy: PathBuf,
+
+        /// Opponents to play (the built-in reference bots if omitted)
+        #[arg(long, num_args = 1..)]
+        suite: Vec<PathBuf>,
+
+        /// Previous version of the strategy, played against the suite with the same seeds
+        #[arg(long)]
+        previous: Option<PathBuf>,
+
+        /// Number of games against each opponent
+        #[arg(short = 'n', long, default_value = "100000")]
+        games: usize,
+
+        /// Seed for reproducible runs (random if omitted); opponent i is played with seed + i
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        /// Rule preset (classic, one-die, no-exact-bust) or path to a JSON rule file
+        #[arg(short, long, default_value = "classic")]
+        rules: String,
+
+        /// CPU budget per decision, in wasmtime fuel units
+        #[arg(long, default_value_t = wasm::DEFAULT_FUEL_PER_DECISION)]
+        fuel_per_decision: u64,
+
+        /// Worker threads to split the games across (one per CPU core if omitted)
+        #[arg(short, long)]
+        workers: Option<usize>,
     },
 }
 
@@ -43,9 +138,65 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    if let Some(Commands::Gauntlet {
+        strategy,
+        suite,
+        previous,
+        games,
+        seed,
+        rules,
+        fuel_per_decision,
+        workers,
+    }) = cli.command
+    {
+        let settings = SimulationSettings {
+            num_games: u32::try_from(games)?,
+            seed: seed.unwrap_or_else(rand::random),
+            rules: RuleSet::from_preset_or_file(&rules)?,
+            recording: RecordingPolicy::None,
+            isolation: Isolation::default(),
+            workers: workers.unwrap_or_else(simulation::default_workers).max(1),
+            priority: Default::default(),
+            early_stopping: None,
+            fuel_per_decision: Some(fuel_per_decision),
+            fuel_per_simulation: None,
+        };
+        return tokio::task::spawn_blocking(move || {
+            run_gauntlet_cli(strategy, suite, previous, settings)
+        })
+        .await?;
+    }
+
     // If simulate command is used, run CLI mode
-    if let Some(Commands::Simulate { strategies, games }) = cli.command {
-        return run_cli_mode(strategies, games).await;
+    if let Some(Commands::Simulate {
+        strategies,
+        games,
+        seed,
+        rules,
+        fuel_per_decision,
+        fuel_per_simulation,
+        isolation,
+        workers,
+        early_stopping,
+    }) = cli.command
+    {
+        let rules = RuleSet::from_preset_or_file(&rules)?;
+        let workers = workers.unwrap_or_else(simulation::default_workers).max(1);
+        // WASI host calls block on the runtime, so the games run off the async threads
+        return tokio::task::spawn_blocking(move || {
+            run_cli_mode(
+                strategies,
+                games,
+                seed,
+                rules,
+                fuel_per_decision,
+                fuel_per_simulation,
+                isolation,
+                workers,
+                early_stopping,
+            )
+        })
+        .await?;
     }
 
     // Web server mode
@@ -57,32 +208,47 @@ async fn main() -> Result<()> {
 
     // Initialize database
     let pool = db::create_pool().await?;
+    db::register_builtin_bots(&pool).await?;
 
     // Create WASM engine
-    let engine = Arc::new(game::create_engine()?);
+    let engine = Arc::new(wasm::create_engine()?);
+    let component_cache = Arc::new(ComponentCache::open(&engine, &bots_dir.join("compiled"))?);
+    db::backfill_world_versions(&pool, &engine, &component_cache).await?;
+    ratings::catch_up(&pool).await?;
+    gauntlet::register_reference_suite(&pool).await?;
 
-    // Create simulation manager
-    let simulation_manager = Arc::new(RwLock::new(SimulationManager::new(
+    // Start the simulation scheduler
+    let simulation_manager = Arc::new(SimulationManager::start(
         pool.clone(),
         engine.clone(),
-    )));
-
-    // Start background task to process simulation queue
-    let manager_clone = simulation_manager.clone();
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-            let mut manager = manager_clone.write().await;
-            manager.check_and_start_next().await;
-        }
-    });
+        component_cache.clone(),
+        cli.max_concurrent_simulations,
+    ));
+    simulation_manager
+        .recover(cli.interrupted_simulations)
+        .await?;
+    tokio::spawn(tournament::run_rounds(
+        pool.clone(),
+        simulation_manager.clone(),
+    ));
+    let ladder = if cli.ladder {
+        let config = LadderConfig {
+            games_per_match: cli.ladder_games.max(1),
+            placement_matches: cli.ladder_placement_matches,
+        };
+        Some(Ladder::start(pool.clone(), simulation_manager.clone(), config).await?)
+    } else {
+        None
+    };
 
     // Create app state
     let state = api::AppState {
         pool,
         engine,
         bots_dir,
+        component_cache,
         simulation_manager,
+        ladder,
     };
 
     // Create router with static file serving
@@ -123,67 +289,229 @@ async fn main() -> Result<()> {
 }
 
 // CLI mode for simulations
-async fn run_cli_mode(strategy_files: Vec<PathBuf>, num_games: usize) -> Result<()> {
-    let engine = game::create_engine()?;
+#[allow(clippy::too_many_arguments)]
+fn run_cli_mode(
+    strategy_files: Vec<PathBuf>,
+    num_games: usize,
+    seed: Option<u64>,
+    rules: RuleSet,
+    fuel_per_decision: u64,
+    fuel_per_simulation: Option<u64>,
+    isolation: Isolation,
+    workers: usize,
+    early_stopping: Option<EarlyStopping>,
+) -> Result<()> {
+    let engine = wasm::create_engine()?;
+    let seed = seed.unwrap_or_else(rand::random);
+    println!(
+        "Using seed {} (pass --seed {} --workers {} to reproduce)",
+        seed, seed, workers
+    );
 
     println!(
         "Loading {} WASM component strategies...",
         strategy_files.len()
     );
-    let mut strategies: Vec<game::WasmStrategy> = Vec::new();
+    let mut sources = Vec::new();
     for path in &strategy_files {
         println!("Loading strategy from: {}", path.display());
-        strategies.push(game::WasmStrategy::from_file(
-            &engine,
-            path.to_str().unwrap(),
-        )?);
+        sources.push(StrategySource::load(&engine, None, path.to_str().unwrap())?);
     }
+    let names: Vec<String> = strategy_files
+        .iter()
+        .map(|path| strategy_name(path))
+        .collect();
 
-    let num_players = strategies.len();
+    let num_players = sources.len();
 
     println!(
-        "Running {} games with {} players...\n",
-        num_games, num_players
+        "Running {} games with {} players on {} worker threads...\n",
+        num_games, num_players, workers
     );
 
-    let mut total_stats = vec![(0u32, 0i64); num_players];
-
-    for game_num in 0..num_games {
-        if game_num % 10_000 == 0 || game_num == num_games - 1 {
-            let progress = (game_num as f64 / num_games as f64 * 100.0) as u32;
+    let games = u32::try_from(num_games)?;
+    let plan = GamePlan {
+        engine: &engine,
+        sources: &sources,
+        names: &names,
+        num_games: games,
+        seed,
+        rules: &rules,
+        isolation,
+        recording: RecordingPolicy::None,
+        memory_limit: None,
+        fuel_per_decision: Some(fuel_per_decision),
+        fuel_per_simulation,
+        early_stopping,
+        resumed: None,
+        shards: simulation::shard_games(games, workers),
+    };
+    let control = SimulationControl::default();
+    let outcome = plan.play(
+        &control,
+        |_| Ok(()),
+        |games_done, _| {
+            let progress = (games_done as f64 / num_games as f64 * 100.0) as u32;
             let bar_width = 50;
             let filled = (progress as usize * bar_width) / 100;
             let bar = "=".repeat(filled) + &"-".repeat(bar_width - filled);
             print!(
                 "\rProgress: [{bar}] {progress:3}% ({}/{} games)",
-                game_num, num_games
+                games_done, num_games
             );
             use std::io::Write;
             std::io::stdout().flush().unwrap();
-        }
+        },
+    )?;
+    println!();
+    if let Some(reason) = control.finish_reason() {
+        println!("Stopped early: {}", reason);
+    }
+
+    let games_played = outcome.games_played;
+    println!("\n=== Final Statistics after {} games ===", games_played);
+    for (i, total) in outcome.totals.iter().enumerate() {
+        let disqualified_note = total
+            .disqualification
+            .map(|reason| format!(" [DISQUALIFIED: {}]", reason))
+            .unwrap_or_default();
+        let interval = Estimate::from_sums(
+            outcome.games_played as u64,
+            total.total_money as f64,
+            total.money_products[i],
+        )
+        .map(|money| format!(" (95% CI ${:.2} to ${:.2})", money.ci95[0], money.ci95[1]))
+        .unwrap_or_default();
+        println!(
+            "Player {} ({}): {} wins, ${:.2} average winnings{}, {} fuel{}",
+            i + 1,
+            names[i],
+            total.games_won,
+            total.total_money as f64 / games_played.max(1) as f64,
+            interval,
+            total.fuel_consumed,
+            disqualified_note
+        );
+    }
 
-        let (results, _, _) = game::simulate_game(&mut strategies)?;
-        for i in 0..num_players {
-            total_stats[i].0 += results[i].0;
-            total_stats[i].1 += results[i].1;
+    let totals = &outcome.totals;
+    for a in 0..totals.len() {
+        for b in a + 1..totals.len() {
+            let player = |i: usize| (i, totals[i].total_money as f64, totals[i].money_products[i]);
+            let products = totals[a].money_products[b];
+            let games = outcome.games_played as u64;
+            if let Some(comparison) = stats::compare(player(a), player(b), products, games) {
+                println!(
+                    "Player {} vs player {}: ${:.2} per game, p = {:.4}{}",
+                    a + 1,
+                    b + 1,
+                    comparison.difference.mean,
+                    comparison.p_value,
+                    if comparison.significant {
+                        " (significant)"
+                    } else {
+                        ""
+                    }
+                );
+            }
         }
     }
-    println!();
 
-    println!("\n=== Final Statistics after {} games ===", num_games);
-    for (i, (wins, money)) in total_stats.iter().enumerate() {
-        let filename = strategy_files[i]
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
+    Ok(())
+}
+
+fn strategy_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+// CLI mode for gauntlets
+fn run_gauntlet_cli(
+    strategy_file: PathBuf,
+    suite_files: Vec<PathBuf>,
+    previous_file: Option<PathBuf>,
+    settings: SimulationSettings,
+) -> Result<()> {
+    let engine = wasm::create_engine()?;
+    println!(
+        "Using seed {} (pass --seed {} --workers {} to reproduce)",
+        settings.seed, settings.seed, settings.workers
+    );
+
+    let suite_files = if suite_files.is_empty() {
+        REFERENCE_BOTS
+            .iter()
+            .map(|(name, _)| PathBuf::from(format!("{}{}", BUILTIN_PREFIX, name)))
+            .collect()
+    } else {
+        suite_files
+    };
+    let load = |path: &PathBuf| -> Result<(StrategySource, String)> {
+        println!("Loading strategy from: {}", path.display());
+        Ok((
+            StrategySource::load(&engine, None, path.to_str().unwrap())?,
+            strategy_name(path),
+        ))
+    };
+    let strategy = load(&strategy_file)?;
+    let previous = previous_file.as_ref().map(load).transpose()?;
+    let suite = suite_files.iter().map(load).collect::<Result<Vec<_>>>()?;
+
+    println!(
+        "\nPlaying {} against {} opponents, {} games each on {} worker threads...",
+        strategy.1,
+        suite.len(),
+        settings.num_games,
+        settings.workers
+    );
+    let matches = gauntlet::run_local(&engine, &settings, &strategy, previous.as_ref(), &suite)?;
+
+    println!(
+        "\n=== Gauntlet scorecard for {} ({} games per opponent) ===",
+        strategy.1, settings.num_games
+    );
+    for row in &matches {
+        let Some(score) = row.score else {
+            println!("vs {}: no games played", row.opponent);
+            continue;
+        };
+        let interval = score
+            .money_ci95
+            .map(|ci95| format!(" (95% CI ${:.2} to ${:.2})", ci95[0], ci95[1]))
+            .unwrap_or_default();
+        let change = row
+            .previous
+            .zip(row.change())
+            .map(|(previous, change)| {
+                format!(
+                    ", previous ${:.2} ({:+.2})",
+                    previous.money_per_game, change
+                )
+            })
+            .unwrap_or_default();
         println!(
-            "Player {} ({}): {} wins, ${:.2} average winnings",
-            i + 1,
-            filename,
-            wins,
-            *money as f64 / num_games as f64
+            "vs {}: ${:.2} per game{}, {:.1}% wins{}",
+            row.opponent,
+            score.money_per_game,
+            interval,
+            score.win_rate * 100.0,
+            change
         );
     }
 
+    let overall = gauntlet::overall_score(matches.iter().map(|row| row.score));
+    let previous_overall = gauntlet::overall_score(matches.iter().map(|row| row.previous));
+    let previous_change = previous_overall.zip(gauntlet::change(overall, previous_overall));
+    match (overall, previous_change) {
+        (Some(overall), Some((previous_overall, change))) => println!(
+            "Overall score: ${:.2} per game, previous ${:.2} ({:+.2})",
+            overall, previous_overall, change
+        ),
+        (Some(overall), None) => println!("Overall score: ${:.2} per game", overall),
+        (None, _) => println!("Overall score: not every match played a game"),
+    }
+
     Ok(())
 }
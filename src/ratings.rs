@@ -0,0 +1,314 @@
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use tokio::sync::Mutex;
+
+/// Skill a bot is assumed to have before its first rated simulation
+pub const INITIAL_MU: f64 = 25.0;
+/// Uncertainty about the skill of a bot that hasn't been rated yet
+pub const INITIAL_SIGMA: f64 = INITIAL_MU / 3.0;
+/// Spread of performance around skill in a single simulation
+const BETA: f64 = INITIAL_SIGMA / 2.0;
+/// Smallest factor a single simulation can shrink the variance by
+const KAPPA: f64 = 0.0001;
+
+/// Rating updates read and then write every participant's rating, so they
+/// run one at a time
+static UPDATE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// A bot's estimated skill and how uncertain it is
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Rating {
+    pub mu: f64,
+    pub sigma: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            mu: INITIAL_MU,
+            sigma: INITIAL_SIGMA,
+        }
+    }
+}
+
+impl Rating {
+    /// Skill the bot has with high confidence (`mu - 3 sigma`), used to rank
+    /// bots so that barely-rated ones don't top the list by luck
+    pub fn conservative(&self) -> f64 {
+        self.mu - 3.0 * self.sigma
+    }
+}
+
+/// Updates the ratings of bots that finished a simulation with the given
+/// ranks (0 is best, equal ranks are ties), using the Weng-Lin Bayesian
+/// approximation of TrueSkill with the full Bradley-Terry pairing: every bot
+/// is compared with every other bot at the table.
+pub fn update(ratings: &[Rating], ranks: &[usize]) -> Vec<Rating> {
+    ratings
+        .iter()
+        .enumerate()
+        .map(|(i, rating)| {
+            let variance = rating.sigma * rating.sigma;
+            let mut omega = 0.0;
+            let mut delta = 0.0;
+            for (q, other) in ratings.iter().enumerate() {
+                if q == i {
+                    continue;
+                }
+                let c = (variance + other.sigma * other.sigma + 2.0 * BETA * BETA).sqrt();
+                let p = 1.0 / (1.0 + ((other.mu - rating.mu) / c).exp());
+                let score = match ranks[i].cmp(&ranks[q]) {
+                    std::cmp::Ordering::Less => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.0,
+                };
+                omega += variance / c * (score - p);
+                let gamma = rating.sigma / c;
+                delta += gamma * variance / (c * c) * p * (1.0 - p);
+            }
+            Rating {
+                mu: rating.mu + omega,
+                sigma: (variance * (1.0 - delta).max(KAPPA)).sqrt(),
+            }
+        })
+        .collect()
+}
+
+#[derive(sqlx::FromRow)]
+struct Seat {
+    bot_id: String,
+    total_money: i64,
+}
+
+/// Rates the bots of a completed simulation from how much money each made,
+/// averaged over its seats when it sat at the table more than once.
+/// Returns `false` when the simulation isn't completed, was already rated or
+/// had fewer than two different bots.
+pub async fn rate_simulation(pool: &SqlitePool, simulation_id: &str) -> Result<bool> {
+    let _guard = UPDATE_LOCK.lock().await;
+    let mut tx = pool.begin().await?;
+    let rated = rate_simulation_locked(&mut tx, simulation_id).await?;
+    tx.commit().await?;
+    Ok(rated)
+}
+
+/// Rates a simulation through `conn`, which the caller holds `UPDATE_LOCK`
+/// and a transaction on
+async fn rate_simulation_locked(conn: &mut SqliteConnection, simulation_id: &str) -> Result<bool> {
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM simulations WHERE id = ?")
+        .bind(simulation_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if status.as_deref() != Some("completed") {
+        return Ok(false);
+    }
+    let rated: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM rating_history WHERE simulation_id = ?")
+            .bind(simulation_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    if rated > 0 {
+        return Ok(false);
+    }
+
+    let seats = sqlx::query_as::<_, Seat>(
+        "SELECT bot_id, total_money FROM simulation_participants
+         WHERE simulation_id = ? ORDER BY player_index",
+    )
+    .bind(simulation_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Money per seat of each distinct bot, in seat order of first appearance
+    let mut bots: Vec<(String, f64, u32)> = Vec::new();
+    for seat in seats {
+        match bots
+            .iter_mut()
+            .find(|(bot_id, _, _)| *bot_id == seat.bot_id)
+        {
+            Some((_, money, count)) => {
+                *money += seat.total_money as f64;
+                *count += 1;
+            }
+            None => bots.push((seat.bot_id, seat.total_money as f64, 1)),
+        }
+    }
+    if bots.len() < 2 {
+        return Ok(false);
+    }
+    let money: Vec<f64> = bots
+        .iter()
+        .map(|(_, money, count)| money / *count as f64)
+        .collect();
+    // Rank is the number of bots that made strictly more money
+    let ranks: Vec<usize> = money
+        .iter()
+        .map(|own| money.iter().filter(|other| *other > own).count())
+        .collect();
+
+    let mut before = Vec::new();
+    for (bot_id, _, _) in &bots {
+        let rating: Option<(f64, f64)> =
+            sqlx::query_as("SELECT mu, sigma FROM bot_ratings WHERE bot_id = ?")
+                .bind(bot_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+        before.push(rating.map_or_else(Rating::default, |(mu, sigma)| Rating { mu, sigma }));
+    }
+    let after = update(&before, &ranks);
+
+    for (((bot_id, _, _), rank), (old, new)) in
+        bots.iter().zip(&ranks).zip(before.iter().zip(&after))
+    {
+        sqlx::query(
+            "INSERT INTO rating_history
+             (bot_id, simulation_id, rank, num_bots, mu_before, sigma_before, mu, sigma)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(bot_id)
+        .bind(simulation_id)
+        .bind(*rank as i64)
+        .bind(bots.len() as i64)
+        .bind(old.mu)
+        .bind(old.sigma)
+        .bind(new.mu)
+        .bind(new.sigma)
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO bot_ratings (bot_id, mu, sigma, simulations, updated_at)
+             VALUES (?, ?, ?, 1, CURRENT_TIMESTAMP)
+             ON CONFLICT(bot_id) DO UPDATE SET
+                 mu = excluded.mu,
+                 sigma = excluded.sigma,
+                 simulations = simulations + 1,
+                 updated_at = excluded.updated_at",
+        )
+        .bind(bot_id)
+        .bind(new.mu)
+        .bind(new.sigma)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(true)
+}
+
+/// Completed simulations in the order they finished
+async fn completed_simulations(
+    conn: &mut SqliteConnection,
+    unrated_only: bool,
+) -> Result<Vec<String>> {
+    let filter = if unrated_only {
+        "AND id NOT IN (SELECT simulation_id FROM rating_history)"
+    } else {
+        ""
+    };
+    let ids = sqlx::query_scalar(&format!(
+        "SELECT id FROM simulations
+         WHERE status = 'completed' {}
+         ORDER BY completed_at, created_at, id",
+        filter
+    ))
+    .fetch_all(conn)
+    .await?;
+    Ok(ids)
+}
+
+/// Throws away every rating and replays all completed simulations in the
+/// order they finished, in one transaction so that a failure part way
+/// leaves the old ratings in place. Returns the number of simulations rated.
+pub async fn recompute(pool: &SqlitePool) -> Result<usize> {
+    let _guard = UPDATE_LOCK.lock().await;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM rating_history")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM bot_ratings")
+        .execute(&mut *tx)
+        .await?;
+
+    let mut rated = 0;
+    for id in completed_simulations(&mut tx, false).await? {
+        if rate_simulation_locked(&mut tx, &id).await? {
+            rated += 1;
+        }
+    }
+    tx.commit().await?;
+    println!("[RATINGS] Recomputed ratings from {} simulations", rated);
+    Ok(rated)
+}
+
+/// Rates completed simulations that haven't been yet, such as those run
+/// before ratings existed or that finished while the server was stopping
+pub async fn catch_up(pool: &SqlitePool) -> Result<usize> {
+    let _guard = UPDATE_LOCK.lock().await;
+
+    let unrated = completed_simulations(&mut *pool.acquire().await?, true).await?;
+    let mut rated = 0;
+    for id in unrated {
+        let mut tx = pool.begin().await?;
+        if rate_simulation_locked(&mut tx, &id).await? {
+            rated += 1;
+        }
+        tx.commit().await?;
+    }
+    if rated > 0 {
+        println!("[RATINGS] Rated {} simulations that were missing", rated);
+    }
+    Ok(rated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winner_gains_and_loser_drops() {
+        let after = update(&[Rating::default(), Rating::default()], &[0, 1]);
+        assert!(after[0].mu > INITIAL_MU);
+        assert!(after[1].mu < INITIAL_MU);
+        assert!((after[0].mu - INITIAL_MU + after[1].mu - INITIAL_MU).abs() < 1e-9);
+        for rating in after {
+            assert!(rating.sigma < INITIAL_SIGMA);
+        }
+    }
+
+    #[test]
+    fn every_result_shrinks_sigma() {
+        let mut ratings = vec![Rating::default(); 3];
+        for _ in 0..50 {
+            let after = update(&ratings, &[0, 1, 2]);
+            for (before, after) in ratings.iter().zip(&after) {
+                assert!(after.sigma < before.sigma);
+            }
+            ratings = after;
+        }
+        assert!(ratings[0].mu > ratings[1].mu && ratings[1].mu > ratings[2].mu);
+        assert!(ratings[0].conservative() > ratings[2].conservative());
+    }
+
+    #[test]
+    fn tied_ranks_are_symmetric() {
+        let after = update(&[Rating::default(), Rating::default()], &[0, 0]);
+        assert_eq!(after[0], after[1]);
+        assert!((after[0].mu - INITIAL_MU).abs() < 1e-9);
+
+        // A tie moves an underdog up and a favourite down by the same amount
+        let favourite = Rating {
+            mu: 30.0,
+            sigma: 4.0,
+        };
+        let underdog = Rating {
+            mu: 20.0,
+            sigma: 4.0,
+        };
+        let after = update(&[favourite, underdog], &[0, 0]);
+        assert!(after[0].mu < favourite.mu && after[1].mu > underdog.mu);
+        assert!((favourite.mu - after[0].mu - (after[1].mu - underdog.mu)).abs() < 1e-9);
+        assert!((after[0].sigma - after[1].sigma).abs() < 1e-9);
+    }
+}
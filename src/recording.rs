@@ -0,0 +1,144 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::DiceRoll;
+use crate::strategy::DisqualificationReason;
+
+/// Most games a single simulation may persist, whatever the policy
+pub const MAX_RECORDED_GAMES: u64 = 1000;
+
+/// Why a turn ended in a bust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BustReason {
+    /// All dice showed 1; score reset to 0
+    SnakeEyes,
+    /// Rolled a 7; turn points lost
+    Seven,
+    /// A single die showed 1; turn points lost
+    SingleOne,
+    /// Third doubles in one turn; score reset to 0
+    ThreeDoubles,
+    /// Landed exactly on the target; score reset to 0
+    ExactTarget,
+}
+
+/// One step of a recorded game. Player numbers are the bot's index in the
+/// simulation (its `player_index`), not its seat.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// Players in the order they take turns
+    SeatOrder { order: Vec<usize> },
+    /// A throw of the dice; `forced` when the player had no choice but to roll
+    Roll {
+        player: usize,
+        dice: DiceRoll,
+        forced: bool,
+    },
+    /// A strategy's answer to `should-roll`
+    Decision { player: usize, roll: bool },
+    /// The turn ended in a bust, leaving the player on `score`
+    Bust {
+        player: usize,
+        reason: BustReason,
+        score: u32,
+    },
+    /// The turn ended with `score` banked
+    Banked { player: usize, score: u32 },
+    /// The player was removed from the game for breaking a limit or crashing
+    Disqualified {
+        player: usize,
+        reason: Option<DisqualificationReason>,
+    },
+    /// First player past the target; everyone else gets a final turn
+    EndgameTriggered { player: usize, score: u32 },
+    /// A player overtook the leader during the endgame
+    LeaderChanged { player: usize, score: u32 },
+    /// Final state of the game
+    GameEnd {
+        winner: usize,
+        final_scores: Vec<u32>,
+        payouts: Vec<i64>,
+    },
+}
+
+/// Collects the events of one game, or does nothing when disabled so that
+/// unrecorded games pay no cost.
+#[derive(Debug, Default)]
+pub struct GameRecorder {
+    events: Option<Vec<GameEvent>>,
+}
+
+impl GameRecorder {
+    pub fn enabled() -> Self {
+        GameRecorder {
+            events: Some(Vec::new()),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        GameRecorder { events: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /// Records an event; the closure only runs when recording is enabled.
+    pub fn record(&mut self, event: impl FnOnce() -> GameEvent) {
+        if let Some(events) = &mut self.events {
+            events.push(event());
+        }
+    }
+
+    pub fn into_events(self) -> Vec<GameEvent> {
+        self.events.unwrap_or_default()
+    }
+}
+
+/// Which games of a simulation are recorded and persisted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RecordingPolicy {
+    #[default]
+    None,
+    /// The first `games` games of the run
+    First { games: u32 },
+    /// Every `every`-th game, starting with the first
+    Sample { every: u32 },
+}
+
+impl RecordingPolicy {
+    pub fn should_record(&self, game_index: u64) -> bool {
+        match *self {
+            RecordingPolicy::None => false,
+            RecordingPolicy::First { games } => game_index < games as u64,
+            RecordingPolicy::Sample { every } => {
+                every > 0 && game_index.is_multiple_of(every as u64)
+            }
+        }
+    }
+
+    /// Number of games this policy records in a run of `num_games`.
+    pub fn recorded_games(&self, num_games: u64) -> u64 {
+        match *self {
+            RecordingPolicy::None => 0,
+            RecordingPolicy::First { games } => num_games.min(games as u64),
+            RecordingPolicy::Sample { every } => num_games.div_ceil(every.max(1) as u64),
+        }
+    }
+
+    pub fn validate(&self, num_games: u64) -> Result<()> {
+        if let RecordingPolicy::Sample { every: 0 } = self {
+            bail!("sample interval must be greater than 0");
+        }
+        if self.recorded_games(num_games) > MAX_RECORDED_GAMES {
+            bail!(
+                "recording policy would store more than {} games",
+                MAX_RECORDED_GAMES
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// How money changes hands once a game has a winner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PayoutRules {
+    /// Dollars each loser pays the winner per point of score difference
+    pub per_point: u32,
+    /// Multiplier on the payment of losers who finish with a score of 0
+    pub zero_score_multiplier: u32,
+}
+
+impl Default for PayoutRules {
+    fn default() -> Self {
+        PayoutRules {
+            per_point: 1,
+            zero_score_multiplier: 2,
+        }
+    }
+}
+
+/// The rules a game of Pig is played under.
+///
+/// `RuleSet::default()` is the classic Pig Pen game described in the README.
+/// Every field has a default, so a partial JSON object only overrides the
+/// rules it mentions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleSet {
+    /// Score a player must pass to trigger the endgame
+    pub target_score: u32,
+    /// Dice rolled per throw (1 or 2; the strategy interface carries two dice)
+    pub num_dice: u32,
+    /// Landing exactly on the target resets the score to 0
+    pub exact_target_busts: bool,
+    /// All dice showing 1 resets the score to 0
+    pub snake_eyes_busts: bool,
+    /// A sum of 7 loses the points of the current turn
+    pub seven_busts: bool,
+    /// Any single die showing 1 loses the points of the current turn
+    pub single_one_busts: bool,
+    /// Three doubles in one turn reset the score to 0
+    pub three_doubles_busts: bool,
+    /// Rolling doubles forces another roll
+    pub forced_roll_on_doubles: bool,
+    pub payout: PayoutRules,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            target_score: 100,
+            num_dice: 2,
+            exact_target_busts: true,
+            snake_eyes_busts: true,
+            seven_busts: true,
+            single_one_busts: false,
+            three_doubles_busts: true,
+            forced_roll_on_doubles: true,
+            payout: PayoutRules::default(),
+        }
+    }
+}
+
+impl RuleSet {
+    /// Names accepted by [`RuleSet::preset`].
+    pub const PRESETS: &'static [&'static str] = &["classic", "one-die", "no-exact-bust"];
+
+    /// Returns one of the built-in rule variants by name.
+    pub fn preset(name: &str) -> Option<RuleSet> {
+        match name {
+            "classic" => Some(RuleSet::default()),
+            // Traditional single-die Pig: a 1 loses the turn, nothing else busts
+            "one-die" => Some(RuleSet {
+                num_dice: 1,
+                exact_target_busts: false,
+                snake_eyes_busts: false,
+                seven_busts: false,
+                single_one_busts: true,
+                three_doubles_busts: false,
+                forced_roll_on_doubles: false,
+                ..RuleSet::default()
+            }),
+            "no-exact-bust" => Some(RuleSet {
+                exact_target_busts: false,
+                ..RuleSet::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Resolves a preset name, or failing that, a path to a JSON rule file.
+    pub fn from_preset_or_file(spec: &str) -> Result<RuleSet> {
+        let rules = match RuleSet::preset(spec) {
+            Some(rules) => rules,
+            None => {
+                let json = fs::read_to_string(spec).with_context(|| {
+                    format!(
+                        "'{}' is neither a rule preset ({}) nor a readable rule file",
+                        spec,
+                        RuleSet::PRESETS.join(", ")
+                    )
+                })?;
+                serde_json::from_str(&json)
+                    .with_context(|| format!("Failed to parse rule file: {}", spec))?
+            }
+        };
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.target_score == 0 {
+            bail!("target_score must be greater than 0");
+        }
+        if !(1..=2).contains(&self.num_dice) {
+            bail!("num_dice must be 1 or 2");
+        }
+        if self.payout.per_point == 0 {
+            bail!("payout.per_point must be greater than 0");
+        }
+        Ok(())
+    }
+
+    /// Whether a score counts as having reached the target. When landing
+    /// exactly on the target busts, only scores past it count.
+    pub fn reaches_target(&self, score: u32) -> bool {
+        if self.exact_target_busts {
+            score > self.target_score
+        } else {
+            score >= self.target_score
+        }
+    }
+
+    /// Amount a loser on `loser_score` pays a winner on `winner_score`.
+    pub fn payment(&self, winner_score: u32, loser_score: u32) -> i64 {
+        let diff = winner_score as i64 - loser_score as i64;
+        let payment = diff * self.payout.per_point as i64;
+        if loser_score == 0 {
+            payment * self.payout.zero_score_multiplier as i64
+        } else {
+            payment
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{simulate_turn, DiceRoll, PlayerState, TurnOutcome};
+    use crate::recording::{BustReason, GameEvent, GameRecorder};
+    use crate::strategy::Scripted;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Rules where a turn only ends when the strategy holds or the variant
+    /// under test kicks in
+    fn quiet_rules() -> RuleSet {
+        RuleSet {
+            target_score: 1000,
+            exact_target_busts: false,
+            snake_eyes_busts: false,
+            seven_busts: false,
+            three_doubles_busts: false,
+            forced_roll_on_doubles: false,
+            ..RuleSet::default()
+        }
+    }
+
+    /// Plays 1000 turns from `start_score` with a fixed seed, returning each
+    /// turn's outcome and dice
+    fn play_turns(
+        rules: &RuleSet,
+        script: &str,
+        start_score: u32,
+    ) -> Vec<(TurnOutcome, Vec<DiceRoll>)> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..1000)
+            .map(|_| {
+                let mut player = PlayerState {
+                    score: start_score,
+                    banked_score: start_score,
+                    turn_start_score: start_score,
+                    doubles_count: 0,
+                };
+                let mut recorder = GameRecorder::enabled();
+                let outcome = simulate_turn(
+                    &mut player,
+                    &[start_score, 0],
+                    0,
+                    &mut Scripted::parse(script).unwrap(),
+                    &mut Vec::new(),
+                    rules,
+                    &mut rng,
+                    &mut recorder,
+                )
+                .unwrap();
+                let dice = recorder
+                    .into_events()
+                    .into_iter()
+                    .filter_map(|event| match event {
+                        GameEvent::Roll { dice, .. } => Some(dice),
+                        _ => None,
+                    })
+                    .collect();
+                (outcome, dice)
+            })
+            .collect()
+    }
+
+    fn points(dice: &[DiceRoll]) -> u32 {
+        dice.iter().map(|(die1, die2)| die1 + die2).sum()
+    }
+
+    fn is_double(&(die1, die2): &DiceRoll) -> bool {
+        die1 == die2
+    }
+
+    #[test]
+    fn validate_rejects_unplayable_rules() {
+        assert!(RuleSet::default().validate().is_ok());
+        for rules in [
+            RuleSet {
+                target_score: 0,
+                ..RuleSet::default()
+            },
+            RuleSet {
+                num_dice: 0,
+                ..RuleSet::default()
+            },
+            RuleSet {
+                num_dice: 3,
+                ..RuleSet::default()
+            },
+            RuleSet {
+                payout: PayoutRules {
+                    per_point: 0,
+                    zero_score_multiplier: 2,
+                },
+                ..RuleSet::default()
+            },
+        ] {
+            assert!(rules.validate().is_err(), "{:?}", rules);
+        }
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for name in RuleSet::PRESETS {
+            let rules = RuleSet::preset(name).unwrap();
+            assert!(rules.validate().is_ok(), "{}", name);
+            assert_eq!(RuleSet::from_preset_or_file(name).unwrap(), rules);
+        }
+        assert_eq!(RuleSet::preset("classic"), Some(RuleSet::default()));
+        assert_eq!(RuleSet::preset("one-die").unwrap().num_dice, 1);
+        assert!(RuleSet::preset("no-such-preset").is_none());
+        assert!(RuleSet::from_preset_or_file("no-such-preset").is_err());
+    }
+
+    #[test]
+    fn exact_target_busts() {
+        let rules = RuleSet {
+            target_score: 30,
+            exact_target_busts: true,
+            ..quiet_rules()
+        };
+        let turns = play_turns(&rules, "r", 0);
+        assert!(turns.iter().any(|(outcome, _)| matches!(
+            outcome,
+            TurnOutcome::Bust {
+                reason: BustReason::ExactTarget,
+                score: 0
+            }
+        )));
+        for (outcome, dice) in turns {
+            match outcome {
+                TurnOutcome::Bust { score: 0, .. } => assert_eq!(points(&dice), 30),
+                TurnOutcome::Banked { score } => assert!(score > 30 && score == points(&dice)),
+                _ => panic!("unexpected outcome {:?}", outcome),
+            }
+        }
+
+        let rules = RuleSet {
+            exact_target_busts: false,
+            ..rules
+        };
+        let turns = play_turns(&rules, "r", 0);
+        assert!(turns
+            .iter()
+            .any(|(outcome, _)| *outcome == TurnOutcome::Banked { score: 30 }));
+        assert!(turns
+            .iter()
+            .all(|(outcome, _)| matches!(outcome, TurnOutcome::Banked { score } if *score >= 30)));
+    }
+
+    #[test]
+    fn single_one_busts() {
+        let rules = RuleSet {
+            single_one_busts: true,
+            ..quiet_rules()
+        };
+        for (outcome, dice) in play_turns(&rules, "r", 10) {
+            let (last, before) = dice.split_last().unwrap();
+            assert!(last.0 == 1 || last.1 == 1);
+            assert!(before.iter().all(|&(die1, die2)| die1 != 1 && die2 != 1));
+            assert_eq!(
+                outcome,
+                TurnOutcome::Bust {
+                    reason: BustReason::SingleOne,
+                    score: 10
+                }
+            );
+        }
+
+        // With one die the second value is always 0 and never counts as a 1
+        let rules = RuleSet {
+            num_dice: 1,
+            ..rules
+        };
+        for (_, dice) in play_turns(&rules, "r", 10) {
+            assert_eq!(dice.last().unwrap().0, 1);
+            assert!(dice.iter().all(|&(_, die2)| die2 == 0));
+        }
+    }
+
+    #[test]
+    fn three_doubles_busts() {
+        let rules = RuleSet {
+            three_doubles_busts: true,
+            forced_roll_on_doubles: true,
+            ..quiet_rules()
+        };
+        let turns = play_turns(&rules, "h", 10);
+        assert!(turns
+            .iter()
+            .any(|(outcome, _)| matches!(outcome, TurnOutcome::Bust { .. })));
+        for (outcome, dice) in turns {
+            if dice.len() == 3 && dice.iter().all(is_double) {
+                assert_eq!(
+                    outcome,
+                    TurnOutcome::Bust {
+                        reason: BustReason::ThreeDoubles,
+                        score: 0
+                    }
+                );
+            } else {
+                assert!(dice.len() <= 3);
+                assert_eq!(
+                    outcome,
+                    TurnOutcome::Banked {
+                        score: 10 + points(&dice)
+                    }
+                );
+            }
+        }
+
+        let rules = RuleSet {
+            three_doubles_busts: false,
+            ..rules
+        };
+        let turns = play_turns(&rules, "h", 10);
+        assert!(turns.iter().any(|(_, dice)| dice.len() > 3));
+        assert!(turns
+            .iter()
+            .all(|(outcome, _)| matches!(outcome, TurnOutcome::Banked { .. })));
+    }
+
+    #[test]
+    fn forced_roll_on_doubles() {
+        let rules = RuleSet {
+            forced_roll_on_doubles: true,
+            ..quiet_rules()
+        };
+        let turns = play_turns(&rules, "h", 0);
+        assert!(turns.iter().any(|(_, dice)| dice.len() > 1));
+        for (_, dice) in turns {
+            let (last, before) = dice.split_last().unwrap();
+            assert!(!is_double(last));
+            assert!(before.iter().all(is_double));
+        }
+
+        let rules = RuleSet {
+            forced_roll_on_doubles: false,
+            ..rules
+        };
+        let turns = play_turns(&rules, "h", 0);
+        assert!(turns.iter().any(|(_, dice)| is_double(&dice[0])));
+        assert!(turns.iter().all(|(_, dice)| dice.len() == 1));
+    }
+}
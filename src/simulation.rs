@@ -1,98 +1,911 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::{collections::VecDeque, sync::Arc};
-use tokio::{sync::mpsc, task};
+use std::{
+    collections::BinaryHeap,
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot, watch},
+    task,
+};
+use uuid::Uuid;
 use wasmtime::Engine;
 
-use crate::{db, game};
+use crate::{
+    cache::ComponentCache,
+    db,
+    events::{EventBus, ProgressUpdate, QueueEntry, QueueSnapshot, SimulationEvent, Standing},
+    game, ratings,
+    recording::{GameEvent, GameRecorder, RecordingPolicy},
+    rules::RuleSet,
+    stats::EarlyStopping,
+    strategy::{DisqualificationReason, Isolation, StrategySource},
+};
 
 pub struct SimulationTask {
     pub simulation_id: String,
     pub bots: Vec<db::Bot>,
     pub num_games: u32,
+    pub seed: u64,
+    pub rules: RuleSet,
+    pub recording: RecordingPolicy,
+    pub isolation: Isolation,
+    pub fuel_per_decision: Option<u64>,
+    pub fuel_per_simulation: Option<u64>,
+    /// Worker threads the games are split across
+    pub workers: usize,
+    pub priority: Priority,
+    /// When to stop before every game has been played; `None` to play them all
+    pub early_stopping: Option<EarlyStopping>,
+    /// Where a paused simulation left off; `None` to start from the first game
+    pub checkpoint: Option<Checkpoint>,
 }
 
+impl SimulationTask {
+    fn queue_entry(&self) -> QueueEntry {
+        QueueEntry {
+            simulation_id: self.simulation_id.clone(),
+            priority: self.priority,
+            num_games: self.num_games,
+            bot_names: self.bots.iter().map(|bot| bot.name.clone()).collect(),
+        }
+    }
+}
+
+/// How to run a simulation, whichever bots sit at the table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationSettings {
+    pub num_games: u32,
+    pub seed: u64,
+    pub rules: RuleSet,
+    pub recording: RecordingPolicy,
+    pub isolation: Isolation,
+    pub workers: usize,
+    pub priority: Priority,
+    /// Checked at fixed points in every worker's games (see `GamePlan`), so
+    /// a seeded run stops after the same games whenever it is repeated with
+    /// the same number of workers, unless it was paused along the way
+    pub early_stopping: Option<EarlyStopping>,
+    pub fuel_per_decision: Option<u64>,
+    pub fuel_per_simulation: Option<u64>,
+}
+
+/// The progress a paused simulation saved
+pub struct Checkpoint {
+    /// The games each worker had left, in game order
+    pub remaining: Vec<Range<u32>>,
+    pub games_completed: u32,
+    pub totals: Vec<ParticipantTotals>,
+}
+
+/// Most worker threads a single simulation may use
+pub const MAX_WORKERS: usize = 256;
+
+/// How often `GamePlan::play` reports progress
+const PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Points in each worker's games at which the early stopping rule is checked;
+/// the rule is checked about every 1% of games
+const CHECK_STAGES: u64 = 100;
+
+/// How often a running simulation publishes a progress event
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One bot's totals over a whole simulation, in seat order
+#[derive(Debug, Clone, Default)]
+pub struct ParticipantTotals {
+    pub games_won: u32,
+    pub total_money: i64,
+    pub peak_memory_bytes: u64,
+    pub fuel_consumed: u64,
+    pub disqualification: Option<DisqualificationReason>,
+    /// Sums over all games of this bot's money times each player's money in
+    /// the same game, by player index. The bot's own entry is the sum of
+    /// squares of its money, which gives its variance; the others give the
+    /// covariances used to compare it with each opponent.
+    pub money_products: Vec<f64>,
+}
+
+impl ParticipantTotals {
+    /// Adds the totals of games played after the ones already counted
+    fn add(&mut self, later: &ParticipantTotals) {
+        self.games_won += later.games_won;
+        self.total_money += later.total_money;
+        if self.money_products.len() < later.money_products.len() {
+            self.money_products.resize(later.money_products.len(), 0.0);
+        }
+        for (sum, later) in self.money_products.iter_mut().zip(&later.money_products) {
+            *sum += later;
+        }
+        self.peak_memory_bytes = self.peak_memory_bytes.max(later.peak_memory_bytes);
+        self.fuel_consumed += later.fuel_consumed;
+        self.disqualification = self.disqualification.or(later.disqualification);
+    }
+}
+
+/// Why a simulation is asked to stop before it has played every game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StopRequest {
+    /// Save a checkpoint to resume from later
+    Pause = 1,
+    /// Finish for good with the results so far
+    Cancel = 2,
+    /// Complete early because the results are already conclusive
+    Finish = 3,
+}
+
+impl StopRequest {
+    /// Status the simulation ends up in
+    fn status(&self) -> &'static str {
+        match self {
+            StopRequest::Pause => "paused",
+            StopRequest::Cancel => "cancelled",
+            StopRequest::Finish => "completed",
+        }
+    }
+}
+
+/// Shared with a running simulation so it can be stopped between games
+#[derive(Debug, Default)]
+pub struct SimulationControl {
+    stop: AtomicU8,
+    finish_reason: Mutex<Option<String>>,
+}
+
+impl SimulationControl {
+    /// Asks the simulation to stop; a cancel overrides an earlier pause, and
+    /// finishing early overrides both
+    pub fn request(&self, request: StopRequest) {
+        self.stop.fetch_max(request as u8, Ordering::Relaxed);
+    }
+
+    /// Completes the simulation early, recording why
+    pub fn finish(&self, reason: String) {
+        *self
+            .finish_reason
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(reason);
+        self.request(StopRequest::Finish);
+    }
+
+    pub fn requested(&self) -> Option<StopRequest> {
+        match self.stop.load(Ordering::Relaxed) {
+            0 => None,
+            1 => Some(StopRequest::Pause),
+            2 => Some(StopRequest::Cancel),
+            _ => Some(StopRequest::Finish),
+        }
+    }
+
+    /// Why the simulation was completed early, if it was
+    pub fn finish_reason(&self) -> Option<String> {
+        self.finish_reason
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// How urgently a queued simulation should run. Higher priorities start
+/// first; simulations of equal priority start in the order they were queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(priority: &str) -> Result<Self> {
+        match priority {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => bail!(
+                "Invalid priority '{}', expected low, normal or high",
+                priority
+            ),
+        }
+    }
+}
+
+/// What happens at startup to simulations that were still running when the
+/// server stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterruptedPolicy {
+    /// Start them over from the first game; seeded runs give the same results
+    #[default]
+    Restart,
+    /// Mark them failed
+    Fail,
+}
+
+impl FromStr for InterruptedPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(policy: &str) -> Result<Self> {
+        match policy {
+            "restart" => Ok(InterruptedPolicy::Restart),
+            "fail" => Ok(InterruptedPolicy::Fail),
+            _ => bail!("Invalid policy '{}', expected restart or fail", policy),
+        }
+    }
+}
+
+/// Runs queued simulations, up to a fixed number at a time.
+///
+/// The queue lives in a scheduler task that is told about new simulations and
+/// finished ones through a channel, and starts the next simulations as soon as
+/// slots free up. Changes to the queue and progress of running simulations are
+/// published as `SimulationEvent`s.
 pub struct SimulationManager {
-    queue: VecDeque<SimulationTask>,
+    commands: mpsc::UnboundedSender<SchedulerCommand>,
     pool: SqlitePool,
-    engine: Arc<Engine>,
-    is_running: bool,
-    completion_rx: Option<mpsc::UnboundedReceiver<()>>,
+    events: EventBus,
+    queue_state: watch::Receiver<QueueSnapshot>,
+}
+
+enum SchedulerCommand {
+    Queue(Box<SimulationTask>),
+    Stop {
+        simulation_id: String,
+        request: StopRequest,
+        reply: oneshot::Sender<Result<bool>>,
+    },
+    Finished(String),
 }
 
 impl SimulationManager {
-    pub fn new(pool: SqlitePool, engine: Arc<Engine>) -> Self {
+    /// Starts the scheduler, which runs up to `max_concurrent` simulations at once
+    pub fn start(
+        pool: SqlitePool,
+        engine: Arc<Engine>,
+        cache: Arc<ComponentCache>,
+        max_concurrent: usize,
+    ) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let events = EventBus::new();
+        let (queue_sender, queue_state) = watch::channel(QueueSnapshot::default());
+        let scheduler = Scheduler {
+            queue: BinaryHeap::new(),
+            next_sequence: 0,
+            running: Vec::new(),
+            max_concurrent: max_concurrent.max(1),
+            pool: pool.clone(),
+            engine,
+            cache,
+            commands: commands.clone(),
+            events: events.clone(),
+            queue_state: queue_sender,
+        };
+        task::spawn(scheduler.run(receiver));
         SimulationManager {
-            queue: VecDeque::new(),
+            commands,
             pool,
-            engine,
-            is_running: false,
-            completion_rx: None,
+            events,
+            queue_state,
         }
     }
 
-    pub fn queue_simulation(&mut self, simulation_id: String, bots: Vec<db::Bot>, num_games: u32) {
-        println!(
-            "[QUEUE] Adding simulation {} to queue (current queue size: {})",
-            simulation_id,
-            self.queue.len()
-        );
+    /// Stores a new pending simulation of `bots`, in seat order, and queues
+    /// it. Returns its id.
+    pub async fn create(&self, bots: Vec<db::Bot>, settings: SimulationSettings) -> Result<String> {
+        let simulation_id = Uuid::new_v4().to_string();
 
-        self.queue.push_back(SimulationTask {
-            simulation_id,
+        // Create simulation record with memory limit
+        let memory_limit_mb = 200u32;
+        sqlx::query(
+            "INSERT INTO simulations
+             (id, status, num_games, memory_limit_mb, seed, rules, recording, isolation, workers,
+              priority, early_stopping, fuel_per_decision, fuel_per_simulation)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&simulation_id)
+        .bind("pending")
+        .bind(settings.num_games)
+        .bind(memory_limit_mb)
+        .bind(settings.seed as i64)
+        .bind(serde_json::to_string(&settings.rules)?)
+        .bind(serde_json::to_string(&settings.recording)?)
+        .bind(serde_json::to_string(&settings.isolation)?)
+        .bind(settings.workers as i64)
+        .bind(settings.priority.as_str())
+        .bind(
+            settings
+                .early_stopping
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .bind(settings.fuel_per_decision.map(|fuel| fuel as i64))
+        .bind(settings.fuel_per_simulation.map(|fuel| fuel as i64))
+        .execute(&self.pool)
+        .await?;
+
+        // Create participant records
+        for (index, bot) in bots.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO simulation_participants (simulation_id, bot_id, player_index)
+                 VALUES (?, ?, ?)",
+            )
+            .bind(&simulation_id)
+            .bind(&bot.id)
+            .bind(index as i32)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        self.queue_simulation(SimulationTask {
+            simulation_id: simulation_id.clone(),
             bots,
-            num_games,
+            num_games: settings.num_games,
+            seed: settings.seed,
+            rules: settings.rules,
+            recording: settings.recording,
+            isolation: settings.isolation,
+            workers: settings.workers,
+            priority: settings.priority,
+            early_stopping: settings.early_stopping,
+            fuel_per_decision: settings.fuel_per_decision,
+            fuel_per_simulation: settings.fuel_per_simulation,
+            checkpoint: None,
         });
 
-        if !self.is_running {
-            self.start_worker();
+        Ok(simulation_id)
+    }
+
+    pub fn queue_simulation(&self, task: SimulationTask) {
+        let _ = self.commands.send(SchedulerCommand::Queue(Box::new(task)));
+    }
+
+    /// Receives every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<SimulationEvent> {
+        self.events.subscribe()
+    }
+
+    /// The simulations running and queued right now
+    pub fn queue_snapshot(&self) -> QueueSnapshot {
+        self.queue_state.borrow().clone()
+    }
+
+    /// Stops a queued, running or paused simulation for good, keeping the
+    /// results of the games played so far. Returns `false` if it is in none of
+    /// those states.
+    pub async fn cancel(&self, simulation_id: &str) -> Result<bool> {
+        self.request_stop(simulation_id, StopRequest::Cancel).await
+    }
+
+    /// Stops a queued or running simulation after the games in progress,
+    /// saving a checkpoint that `resume` continues from. Returns `false` if it
+    /// is neither queued nor running.
+    pub async fn pause(&self, simulation_id: &str) -> Result<bool> {
+        self.request_stop(simulation_id, StopRequest::Pause).await
+    }
+
+    async fn request_stop(&self, simulation_id: &str, request: StopRequest) -> Result<bool> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(SchedulerCommand::Stop {
+                simulation_id: simulation_id.to_string(),
+                request,
+                reply,
+            })
+            .map_err(|_| anyhow!("Simulation scheduler has stopped"))?;
+        response.await?
+    }
+
+    /// Queues a paused simulation again to continue from its checkpoint.
+    /// Returns `false` if it isn't paused.
+    pub async fn resume(&self, simulation_id: &str) -> Result<bool> {
+        let resumed = sqlx::query(
+            "UPDATE simulations SET status = 'pending' WHERE id = ? AND status = 'paused'",
+        )
+        .bind(simulation_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+        if !resumed {
+            return Ok(false);
+        }
+
+        match load_task(&self.pool, simulation_id).await {
+            Ok(task) => {
+                self.events
+                    .publish(SimulationEvent::status(simulation_id, "pending"));
+                self.queue_simulation(task);
+                Ok(true)
+            }
+            Err(e) => {
+                mark_failed(
+                    &self.pool,
+                    simulation_id,
+                    &format!("Could not resume: {}", e),
+                )
+                .await?;
+                Err(e)
+            }
         }
     }
 
-    fn start_worker(&mut self) {
-        println!("start_worker called...");
-        if let Some(task) = self.queue.pop_front() {
-            self.is_running = true;
-            let pool = self.pool.clone();
-            let engine = self.engine.clone();
+    /// Rebuilds the queue from the `simulations` table after a restart:
+    /// simulations that were running when the server stopped are handled
+    /// according to `interrupted`, then every pending one is queued again in
+    /// the order it was created.
+    pub async fn recover(&self, interrupted: InterruptedPolicy) -> Result<()> {
+        let pool = &self.pool;
+        let running: Vec<String> =
+            sqlx::query_scalar("SELECT id FROM simulations WHERE status = 'running'")
+                .fetch_all(pool)
+                .await?;
+        for simulation_id in &running {
+            match interrupted {
+                InterruptedPolicy::Restart => {
+                    println!(
+                        "[QUEUE] Restarting simulation {} interrupted by a server restart",
+                        simulation_id
+                    );
+                    discard_progress(pool, simulation_id).await?;
+                }
+                InterruptedPolicy::Fail => {
+                    println!(
+                        "[QUEUE] Failing simulation {} interrupted by a server restart",
+                        simulation_id
+                    );
+                    mark_failed(pool, simulation_id, "Interrupted by a server restart").await?;
+                }
+            }
+        }
 
-            let (tx, rx) = mpsc::unbounded_channel();
-            self.completion_rx = Some(rx);
+        let pending: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM simulations WHERE status = 'pending' ORDER BY created_at, rowid",
+        )
+        .fetch_all(pool)
+        .await?;
+        for simulation_id in &pending {
+            match load_task(pool, simulation_id).await {
+                Ok(task) => self.queue_simulation(task),
+                Err(e) => {
+                    println!(
+                        "[QUEUE] Could not restore simulation {}: {}",
+                        simulation_id, e
+                    );
+                    mark_failed(pool, simulation_id, &format!("Could not restore: {}", e)).await?;
+                }
+            }
+        }
 
-            task::spawn(async move {
-                let _ = run_simulation(task, pool, engine).await;
-                let _ = tx.send(());
-            });
-        } else {
-            self.is_running = false;
+        Ok(())
+    }
+}
+
+/// Rebuilds a queued simulation from what was stored when it was started
+async fn load_task(pool: &SqlitePool, simulation_id: &str) -> Result<SimulationTask> {
+    let simulation = sqlx::query_as::<_, db::Simulation>("SELECT * FROM simulations WHERE id = ?")
+        .bind(simulation_id)
+        .fetch_one(pool)
+        .await?;
+    let bots = sqlx::query_as::<_, db::Bot>(
+        "SELECT b.* FROM simulation_participants sp
+         JOIN bots b ON b.id = sp.bot_id
+         WHERE sp.simulation_id = ?
+         ORDER BY sp.player_index",
+    )
+    .bind(simulation_id)
+    .fetch_all(pool)
+    .await?;
+    if bots.is_empty() {
+        bail!("simulation has no participants");
+    }
+
+    fn parse_json<T: DeserializeOwned + Default>(json: Option<&str>) -> Result<T> {
+        Ok(json
+            .map(serde_json::from_str)
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    let checkpoint = match simulation.checkpoint.as_deref() {
+        Some(remaining) => {
+            let participants = sqlx::query_as::<_, db::SimulationParticipant>(
+                "SELECT * FROM simulation_participants
+                 WHERE simulation_id = ?
+                 ORDER BY player_index",
+            )
+            .bind(simulation_id)
+            .fetch_all(pool)
+            .await?;
+            Some(Checkpoint {
+                remaining: serde_json::from_str(remaining)?,
+                games_completed: simulation.games_completed,
+                totals: participants
+                    .iter()
+                    .map(|participant| {
+                        Ok(ParticipantTotals {
+                            games_won: participant.games_won as u32,
+                            total_money: participant.total_money,
+                            peak_memory_bytes: participant.peak_memory_bytes.unwrap_or(0) as u64,
+                            fuel_consumed: participant.fuel_consumed.unwrap_or(0) as u64,
+                            disqualification: participant
+                                .disqualification_reason
+                                .as_deref()
+                                .and_then(|reason| reason.parse().ok()),
+                            money_products: parse_json(participant.money_products.as_deref())?,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            })
+        }
+        None => None,
+    };
+
+    // Simulations queued before seeds were stored get one now, so a restart
+    // after this one still replays the same games
+    let seed = match simulation.seed {
+        Some(seed) => seed as u64,
+        None => {
+            let seed = random_seed();
+            sqlx::query("UPDATE simulations SET seed = ? WHERE id = ?")
+                .bind(seed as i64)
+                .bind(simulation_id)
+                .execute(pool)
+                .await?;
+            seed
         }
-        println!("start_worker exited.");
+    };
+
+    Ok(SimulationTask {
+        simulation_id: simulation.id,
+        bots,
+        num_games: simulation.num_games,
+        seed,
+        rules: parse_json(simulation.rules.as_deref())?,
+        recording: parse_json(simulation.recording.as_deref())?,
+        isolation: parse_json(simulation.isolation.as_deref())?,
+        workers: simulation.workers.map_or(1, |workers| workers as usize),
+        priority: simulation
+            .priority
+            .as_deref()
+            .map(Priority::from_str)
+            .transpose()?
+            .unwrap_or_default(),
+        fuel_per_decision: simulation.fuel_per_decision.map(|fuel| fuel as u64),
+        fuel_per_simulation: simulation.fuel_per_simulation.map(|fuel| fuel as u64),
+        early_stopping: parse_json(simulation.early_stopping.as_deref())?,
+        checkpoint,
+    })
+}
+
+/// Puts a simulation back to `pending`, dropping anything a partial run saved
+async fn discard_progress(pool: &SqlitePool, simulation_id: &str) -> Result<()> {
+    sqlx::query("DELETE FROM game_records WHERE simulation_id = ?")
+        .bind(simulation_id)
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM simulation_snapshots WHERE simulation_id = ?")
+        .bind(simulation_id)
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "UPDATE simulation_participants
+         SET games_won = 0, total_money = 0, peak_memory_bytes = NULL, disqualified = FALSE,
+             disqualification_reason = NULL, fuel_consumed = NULL, money_products = NULL
+         WHERE simulation_id = ?",
+    )
+    .bind(simulation_id)
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "UPDATE simulations
+         SET status = 'pending', games_completed = 0, started_at = NULL, checkpoint = NULL,
+             stop_reason = NULL
+         WHERE id = ?",
+    )
+    .bind(simulation_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Stores each bot's totals over the `games_completed` games played so far.
+/// With several workers these are the games each worker had finished when the
+/// snapshot was taken, not the first `games_completed` games of the run.
+async fn save_snapshot(
+    pool: &SqlitePool,
+    simulation_id: &str,
+    games_completed: u32,
+    totals: &[ParticipantTotals],
+) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    for (index, total) in totals.iter().enumerate() {
+        sqlx::query(
+            "INSERT OR REPLACE INTO simulation_snapshots
+                 (simulation_id, games_completed, player_index, games_won, total_money)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(simulation_id)
+        .bind(games_completed)
+        .bind(index as i32)
+        .bind(total.games_won as i32)
+        .bind(total.total_money)
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn mark_failed(pool: &SqlitePool, simulation_id: &str, error_message: &str) -> Result<()> {
+    sqlx::query(
+        "UPDATE simulations
+         SET status = 'failed', error_message = ?, completed_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+    )
+    .bind(error_message)
+    .bind(simulation_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A queued simulation, ordered by priority and then by when it was queued
+struct QueuedSimulation {
+    priority: Priority,
+    sequence: u64,
+    task: SimulationTask,
+}
+
+impl Ord for QueuedSimulation {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for QueuedSimulation {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedSimulation {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
     }
+}
+
+impl Eq for QueuedSimulation {}
+
+/// A simulation the scheduler has started
+struct RunningSimulation {
+    entry: QueueEntry,
+    control: Arc<SimulationControl>,
+}
+
+struct Scheduler {
+    queue: BinaryHeap<QueuedSimulation>,
+    next_sequence: u64,
+    /// Simulations currently running, in the order they started
+    running: Vec<RunningSimulation>,
+    max_concurrent: usize,
+    pool: SqlitePool,
+    engine: Arc<Engine>,
+    cache: Arc<ComponentCache>,
+    /// Handed to running simulations to report back when they finish
+    commands: mpsc::UnboundedSender<SchedulerCommand>,
+    events: EventBus,
+    queue_state: watch::Sender<QueueSnapshot>,
+}
 
-    pub async fn check_and_start_next(&mut self) {
-        // Check if the current simulation has completed
-        if let Some(rx) = &mut self.completion_rx {
-            if rx.try_recv().is_ok() {
-                println!("[MANAGER] Simulation completed, resetting is_running flag");
-                self.is_running = false;
-                self.completion_rx = None;
+impl Scheduler {
+    async fn run(mut self, mut receiver: mpsc::UnboundedReceiver<SchedulerCommand>) {
+        while let Some(command) = receiver.recv().await {
+            match command {
+                SchedulerCommand::Queue(task) => {
+                    println!(
+                        "[QUEUE] Adding simulation {} to queue with {} priority (current queue size: {}, running: {})",
+                        task.simulation_id,
+                        task.priority.as_str(),
+                        self.queue.len(),
+                        self.running.len()
+                    );
+                    self.queue.push(QueuedSimulation {
+                        priority: task.priority,
+                        sequence: self.next_sequence,
+                        task: *task,
+                    });
+                    self.next_sequence += 1;
+                }
+                SchedulerCommand::Stop {
+                    simulation_id,
+                    request,
+                    reply,
+                } => {
+                    let _ = reply.send(self.stop(&simulation_id, request).await);
+                }
+                SchedulerCommand::Finished(simulation_id) => {
+                    println!("[MANAGER] Simulation {} finished", simulation_id);
+                    self.running
+                        .retain(|running| running.entry.simulation_id != simulation_id);
+                }
             }
+            self.start_queued();
+            self.publish_queue();
         }
+    }
+
+    /// Publishes the running and queued simulations if they changed
+    fn publish_queue(&self) {
+        let mut queued: Vec<&QueuedSimulation> = self.queue.iter().collect();
+        queued.sort_by(|a, b| b.cmp(a));
+        let snapshot = QueueSnapshot {
+            running: self
+                .running
+                .iter()
+                .map(|running| running.entry.clone())
+                .collect(),
+            queued: queued
+                .into_iter()
+                .map(|queued| queued.task.queue_entry())
+                .collect(),
+        };
 
-        // Start next simulation if not running and queue has tasks
-        if !self.is_running && !self.queue.is_empty() {
-            self.start_worker();
+        let changed = *self.queue_state.borrow() != snapshot;
+        if changed {
+            self.queue_state.send_replace(snapshot.clone());
+            self.events.publish(SimulationEvent::Queue(snapshot));
+        }
+    }
+
+    async fn stop(&mut self, simulation_id: &str, request: StopRequest) -> Result<bool> {
+        let running = self
+            .running
+            .iter()
+            .find(|running| running.entry.simulation_id == simulation_id);
+        if let Some(running) = running {
+            println!(
+                "[MANAGER] Stopping simulation {} ({})",
+                simulation_id,
+                request.status()
+            );
+            running.control.request(request);
+            return Ok(true);
+        }
+
+        let queued = self.queue.len();
+        self.queue
+            .retain(|queued| queued.task.simulation_id != simulation_id);
+        if self.queue.len() < queued {
+            println!(
+                "[QUEUE] Removed simulation {} from the queue ({})",
+                simulation_id,
+                request.status()
+            );
+            // Pausing a simulation queued to resume keeps its checkpoint
+            let query = match request {
+                StopRequest::Pause => "UPDATE simulations SET status = ? WHERE id = ?",
+                StopRequest::Cancel | StopRequest::Finish => {
+                    "UPDATE simulations
+                     SET status = ?, checkpoint = NULL, completed_at = CURRENT_TIMESTAMP
+                     WHERE id = ?"
+                }
+            };
+            sqlx::query(query)
+                .bind(request.status())
+                .bind(simulation_id)
+                .execute(&self.pool)
+                .await?;
+            self.events
+                .publish(SimulationEvent::status(simulation_id, request.status()));
+            return Ok(true);
+        }
+
+        // A paused simulation can still be cancelled, keeping its results so far
+        if request == StopRequest::Cancel {
+            let cancelled = sqlx::query(
+                "UPDATE simulations
+                 SET status = 'cancelled', checkpoint = NULL, completed_at = CURRENT_TIMESTAMP
+                 WHERE id = ? AND status = 'paused'",
+            )
+            .bind(simulation_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected()
+                > 0;
+            if cancelled {
+                self.events
+                    .publish(SimulationEvent::status(simulation_id, request.status()));
+            }
+            return Ok(cancelled);
+        }
+
+        Ok(false)
+    }
+
+    fn start_queued(&mut self) {
+        while self.running.len() < self.max_concurrent {
+            let Some(QueuedSimulation { task, .. }) = self.queue.pop() else {
+                break;
+            };
+
+            let simulation_id = task.simulation_id.clone();
+            let control = Arc::new(SimulationControl::default());
+            self.running.push(RunningSimulation {
+                entry: task.queue_entry(),
+                control: control.clone(),
+            });
+            let simulation = task::spawn(run_simulation(
+                task,
+                self.pool.clone(),
+                self.engine.clone(),
+                self.cache.clone(),
+                control,
+                self.events.clone(),
+            ));
+            let commands = self.commands.clone();
+            task::spawn(async move {
+                // Report back even if the simulation panicked, so its slot is freed
+                match simulation.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => println!("[SIMULATION {}] Error: {}", simulation_id, e),
+                    Err(e) => println!("[SIMULATION {}] Panicked: {}", simulation_id, e),
+                }
+                let _ = commands.send(SchedulerCommand::Finished(simulation_id));
+            });
         }
     }
 }
 
-async fn run_simulation(task: SimulationTask, pool: SqlitePool, engine: Arc<Engine>) -> Result<()> {
-    println!(
-        "[SIMULATION {}] Starting simulation with {} bots for {} games",
-        task.simulation_id,
-        task.bots.len(),
-        task.num_games
-    );
+async fn run_simulation(
+    task: SimulationTask,
+    pool: SqlitePool,
+    engine: Arc<Engine>,
+    cache: Arc<ComponentCache>,
+    control: Arc<SimulationControl>,
+    events: EventBus,
+) -> Result<()> {
+    match &task.checkpoint {
+        Some(checkpoint) => println!(
+            "[SIMULATION {}] Resuming simulation with {} bots after {} of {} games (seed {})",
+            task.simulation_id,
+            task.bots.len(),
+            checkpoint.games_completed,
+            task.num_games,
+            task.seed
+        ),
+        None => println!(
+            "[SIMULATION {}] Starting simulation with {} bots for {} games (seed {})",
+            task.simulation_id,
+            task.bots.len(),
+            task.num_games,
+            task.seed
+        ),
+    }
 
     // Update status to running
     sqlx::query(
@@ -101,73 +914,144 @@ async fn run_simulation(task: SimulationTask, pool: SqlitePool, engine: Arc<Engi
     .bind(&task.simulation_id)
     .execute(&pool)
     .await?;
+    events.publish(SimulationEvent::status(&task.simulation_id, "running"));
 
     // Clone values before moving task
     let simulation_id = task.simulation_id.clone();
-    let num_games = task.num_games;
+    let bot_ids: Vec<String> = task.bots.iter().map(|bot| bot.id.clone()).collect();
 
     // Run the simulation in a blocking task
     let pool_clone = pool.clone();
     let simulation_id_clone = simulation_id.clone();
+    let control_clone = control.clone();
+    let events_clone = events.clone();
     let simulation_result = task::spawn_blocking(move || {
-        run_simulation_sync(task, engine, pool_clone, simulation_id_clone)
+        run_simulation_sync(
+            task,
+            engine,
+            cache,
+            pool_clone,
+            simulation_id_clone,
+            &control_clone,
+            &events_clone,
+        )
     })
     .await?;
 
     match simulation_result {
-        Ok((results, usage_stats, disqualified, bot_ids)) => {
-            println!(
-                "[SIMULATION {}] Simulation completed successfully",
-                bot_ids.0
-            );
+        Ok(outcome) => {
+            let finished = outcome.remaining.iter().all(Range::is_empty);
+            let stopped = control.requested().filter(|_| !finished);
+            let stop_reason = stopped
+                .filter(|request| *request == StopRequest::Finish)
+                .and_then(|_| control.finish_reason());
+            match &stop_reason {
+                Some(reason) => println!(
+                    "[SIMULATION {}] Simulation completed early after {} games ({})",
+                    simulation_id, outcome.games_played, reason
+                ),
+                None => println!(
+                    "[SIMULATION {}] Simulation {} after {} games",
+                    simulation_id,
+                    stopped.map_or("completed successfully", |request| request.status()),
+                    outcome.games_played
+                ),
+            }
+
+            save_snapshot(&pool, &simulation_id, outcome.games_played, &outcome.totals).await?;
 
             // Log results for each bot
-            for (index, (games_won, total_money)) in results.iter().enumerate() {
-                let win_rate = (*games_won as f64 / num_games as f64) * 100.0;
-                let avg_money = *total_money as f64 / num_games as f64;
-                let peak_memory = usage_stats[index];
-                let is_disqualified = disqualified[index];
+            let games_played = outcome.games_played.max(1);
+            for (index, (bot_id, participant)) in bot_ids.iter().zip(&outcome.totals).enumerate() {
+                let win_rate = (participant.games_won as f64 / games_played as f64) * 100.0;
+                let avg_money = participant.total_money as f64 / games_played as f64;
+                let disqualified_note = participant
+                    .disqualification
+                    .map(|reason| format!(" [DISQUALIFIED: {}]", reason))
+                    .unwrap_or_default();
 
                 println!(
-                    "[SIMULATION {}] Bot {} (index {}): {} wins ({:.1}%), ${} total (${:.2} avg/game), {} bytes peak memory{}",
-                    bot_ids.0,
-                    bot_ids.1[index],
+                    "[SIMULATION {}] Bot {} (index {}): {} wins ({:.1}%), ${} total (${:.2} avg/game), {} bytes peak memory, {} fuel{}",
+                    simulation_id,
+                    bot_id,
                     index,
-                    games_won,
+                    participant.games_won,
                     win_rate,
-                    total_money,
+                    participant.total_money,
                     avg_money,
-                    peak_memory,
-                    if is_disqualified { " [DISQUALIFIED]" } else { "" }
+                    participant.peak_memory_bytes,
+                    participant.fuel_consumed,
+                    disqualified_note
                 );
 
                 sqlx::query(
                     "UPDATE simulation_participants
-                     SET games_won = ?, total_money = ?, peak_memory_bytes = ?, disqualified = ?
+                     SET games_won = ?, total_money = ?, peak_memory_bytes = ?, disqualified = ?,
+                         disqualification_reason = ?, fuel_consumed = ?, money_products = ?
                      WHERE simulation_id = ? AND bot_id = ? AND player_index = ?",
                 )
-                .bind(*games_won as i32)
-                .bind(*total_money)
-                .bind(peak_memory as i64)
-                .bind(is_disqualified)
-                .bind(&bot_ids.0)
-                .bind(&bot_ids.1[index])
+                .bind(participant.games_won as i32)
+                .bind(participant.total_money)
+                .bind(participant.peak_memory_bytes as i64)
+                .bind(participant.disqualification.is_some())
+                .bind(participant.disqualification.map(|reason| reason.as_str()))
+                .bind(participant.fuel_consumed as i64)
+                .bind(serde_json::to_string(&participant.money_products)?)
+                .bind(&simulation_id)
+                .bind(bot_id)
                 .bind(index as i32)
                 .execute(&pool)
                 .await?;
             }
 
             // Update simulation status
-            sqlx::query(
-                "UPDATE simulations
-                 SET status = 'completed', completed_at = CURRENT_TIMESTAMP
-                 WHERE id = ?",
-            )
-            .bind(&bot_ids.0)
-            .execute(&pool)
-            .await?;
+            match stopped {
+                Some(StopRequest::Pause) => {
+                    let remaining: Vec<_> = outcome
+                        .remaining
+                        .into_iter()
+                        .filter(|games| !games.is_empty())
+                        .collect();
+                    sqlx::query(
+                        "UPDATE simulations
+                         SET status = 'paused', games_completed = ?, checkpoint = ?
+                         WHERE id = ?",
+                    )
+                    .bind(outcome.games_played)
+                    .bind(serde_json::to_string(&remaining)?)
+                    .bind(&simulation_id)
+                    .execute(&pool)
+                    .await?;
+                    events.publish(SimulationEvent::status(&simulation_id, "paused"));
+                }
+                _ => {
+                    let status = stopped.map_or("completed", |request| request.status());
+                    sqlx::query(
+                        "UPDATE simulations
+                         SET status = ?, games_completed = ?, checkpoint = NULL, stop_reason = ?,
+                             completed_at = CURRENT_TIMESTAMP
+                         WHERE id = ?",
+                    )
+                    .bind(status)
+                    .bind(outcome.games_played)
+                    .bind(&stop_reason)
+                    .bind(&simulation_id)
+                    .execute(&pool)
+                    .await?;
+                    if status == "completed" {
+                        // A failed rating update is caught up at the next startup
+                        if let Err(e) = ratings::rate_simulation(&pool, &simulation_id).await {
+                            println!(
+                                "[SIMULATION {}] Failed to update ratings: {}",
+                                simulation_id, e
+                            );
+                        }
+                    }
+                    events.publish(SimulationEvent::status(&simulation_id, status));
+                }
+            }
 
-            println!("[SIMULATION {}] Results saved to database", bot_ids.0);
+            println!("[SIMULATION {}] Results saved to database", simulation_id);
         }
         Err(e) => {
             println!("[SIMULATION {}] Simulation failed: {}", simulation_id, e);
@@ -182,86 +1066,757 @@ async fn run_simulation(task: SimulationTask, pool: SqlitePool, engine: Arc<Engi
             .bind(&simulation_id)
             .execute(&pool)
             .await?;
+            events.publish(SimulationEvent::status(&simulation_id, "failed"));
         }
     }
 
     Ok(())
 }
 
+/// Plays the games a simulation has left, adding them to its checkpoint when
+/// it is resumed
 fn run_simulation_sync(
     task: SimulationTask,
     engine: Arc<Engine>,
+    cache: Arc<ComponentCache>,
     pool: SqlitePool,
     simulation_id: String,
-) -> Result<(Vec<(u32, i64)>, Vec<u64>, Vec<bool>, (String, Vec<String>))> {
-    let mut strategies = Vec::new();
-    let mut bot_ids = Vec::new();
+    control: &SimulationControl,
+    events: &EventBus,
+) -> Result<PlayOutcome> {
+    let (shards, games_before, mut totals) = match &task.checkpoint {
+        Some(checkpoint) => (
+            checkpoint.remaining.clone(),
+            checkpoint.games_completed,
+            checkpoint.totals.clone(),
+        ),
+        None => (
+            shard_games(task.num_games, task.workers),
+            0,
+            vec![ParticipantTotals::default(); task.bots.len()],
+        ),
+    };
+
+    // Bots disqualified before a pause sit out the rest of the run
+    let sources = task
+        .bots
+        .iter()
+        .zip(&totals)
+        .map(|(bot, previous)| match previous.disqualification {
+            Some(reason) => Ok(StrategySource::Disqualified(reason)),
+            None => StrategySource::load(&engine, Some(&cache), &bot.file_path),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let names: Vec<String> = task.bots.iter().map(|bot| bot.name.clone()).collect();
 
-    // Calculate memory limit: 200MB / number of bots
+    // Calculate memory limit: 200MB / number of bot instances, as every
+    // worker runs its own instance of each bot
     let memory_limit_mb = 200_u64;
-    let memory_limit_per_bot = (memory_limit_mb * 1024 * 1024) / task.bots.len() as u64;
+    let instances = (task.bots.len() * shards.len().max(1)) as u64;
+    let memory_limit_per_bot = (memory_limit_mb * 1024 * 1024) / instances;
 
-    for bot in &task.bots {
-        let wasm_bytes = std::fs::read(&bot.file_path)?;
-        let mut strategy = game::WasmStrategy::new(&engine, &wasm_bytes)?;
-        strategy.set_memory_limit(memory_limit_per_bot);
-        strategies.push(strategy);
-        bot_ids.push(bot.id.clone());
+    let plan = GamePlan {
+        engine: &engine,
+        sources: &sources,
+        names: &names,
+        num_games: task.num_games,
+        seed: task.seed,
+        rules: &task.rules,
+        isolation: task.isolation,
+        recording: task.recording,
+        memory_limit: Some(memory_limit_per_bot),
+        fuel_per_decision: task.fuel_per_decision,
+        fuel_per_simulation: task.fuel_per_simulation,
+        early_stopping: task.early_stopping,
+        resumed: task.checkpoint.as_ref(),
+        shards,
+    };
+
+    // Update progress every 1% of games or every 5000 games, whichever is larger
+    let update_interval = std::cmp::max(5000, std::cmp::max(1, task.num_games / 100));
+    let mut last_update = games_before;
+
+    // Snapshot the standings about every 1% of games
+    let snapshot_interval = std::cmp::max(1, task.num_games / 100);
+    let mut last_snapshot = games_before;
+
+    // Progress events are published less often than they are polled, but
+    // disqualifications as soon as they are seen
+    let started = Instant::now();
+    let mut last_event: Option<(Instant, u32)> = None;
+    let mut reported: Vec<bool> = totals
+        .iter()
+        .map(|total| total.disqualification.is_some())
+        .collect();
+
+    let handle = tokio::runtime::Handle::current();
+    let mut outcome = plan.play(
+        control,
+        |game| {
+            let events = serde_json::to_string(&game.events)?;
+            handle.block_on(async {
+                sqlx::query(
+                    "INSERT INTO game_records (simulation_id, game_index, winner_index, events)
+                     VALUES (?, ?, ?, ?)",
+                )
+                .bind(&simulation_id)
+                .bind(game.game_index as i64)
+                .bind(game.winner_index.map(|i| i as i32))
+                .bind(&events)
+                .execute(&pool)
+                .await
+            })?;
+            Ok(())
+        },
+        |games_played, live| {
+            let games_done = games_before + games_played;
+            let mut standings = totals.clone();
+            for (total, later) in standings.iter_mut().zip(live) {
+                total.add(later);
+            }
+
+            for (index, (bot, total)) in task.bots.iter().zip(&standings).enumerate() {
+                if let (Some(reason), false) = (total.disqualification, reported[index]) {
+                    reported[index] = true;
+                    events.publish(SimulationEvent::Disqualified {
+                        simulation_id: simulation_id.clone(),
+                        player_index: index,
+                        bot_id: bot.id.clone(),
+                        bot_name: bot.name.clone(),
+                        reason,
+                    });
+                }
+            }
+
+            let due = match last_event {
+                Some((at, games)) => {
+                    at.elapsed() >= PROGRESS_EVENT_INTERVAL
+                        || (games_done == task.num_games && games != games_done)
+                }
+                None => true,
+            };
+            if due {
+                last_event = Some((Instant::now(), games_done));
+                let elapsed = started.elapsed().as_secs_f64();
+                let games_per_second = if elapsed > 0.0 {
+                    games_played as f64 / elapsed
+                } else {
+                    0.0
+                };
+                let eta_seconds = (games_per_second > 0.0)
+                    .then(|| task.num_games.saturating_sub(games_done) as f64 / games_per_second);
+                events.publish(SimulationEvent::Progress(ProgressUpdate {
+                    simulation_id: simulation_id.clone(),
+                    games_completed: games_done,
+                    num_games: task.num_games,
+                    games_per_second,
+                    eta_seconds,
+                    standings: standings_of(&task.bots, &standings, games_done),
+                }));
+            }
+
+            if games_done >= last_snapshot + snapshot_interval {
+                last_snapshot = games_done;
+                // A missing snapshot only leaves a gap in the convergence history
+                if let Err(e) =
+                    handle.block_on(save_snapshot(&pool, &simulation_id, games_done, &standings))
+                {
+                    tracing::warn!(
+                        "Failed to save snapshot of simulation {} after {} games: {e:#}",
+                        simulation_id,
+                        games_done
+                    );
+                }
+            }
+
+            if games_done >= last_update + update_interval
+                || (games_done == task.num_games && games_done != last_update)
+            {
+                last_update = games_done;
+                handle.block_on(async {
+                    let _ = sqlx::query("UPDATE simulations SET games_completed = ? WHERE id = ?")
+                        .bind(games_done)
+                        .bind(&simulation_id)
+                        .execute(&pool)
+                        .await;
+                });
+            }
+        },
+    )?;
+
+    for (total, later) in totals.iter_mut().zip(&outcome.totals) {
+        total.add(later);
     }
+    outcome.totals = totals;
+    outcome.games_played += games_before;
+    Ok(outcome)
+}
 
-    let num_players = strategies.len();
-    let mut total_stats = vec![(0u32, 0i64); num_players];
-    let mut total_usage_stats: Vec<u64> = vec![0; num_players]; // peak_memory
-    let mut permanently_disqualified = vec![false; num_players]; // Track permanently disqualified bots
+/// Each bot's totals so far, in the shape they are published
+fn standings_of(bots: &[db::Bot], totals: &[ParticipantTotals], games_done: u32) -> Vec<Standing> {
+    bots.iter()
+        .zip(totals)
+        .enumerate()
+        .map(|(player_index, (bot, total))| Standing {
+            player_index,
+            bot_id: bot.id.clone(),
+            bot_name: bot.name.clone(),
+            games_won: total.games_won,
+            total_money: total.total_money,
+            average_money_per_game: total.total_money as f64 / games_done.max(1) as f64,
+            disqualification_reason: total.disqualification,
+        })
+        .collect()
+}
 
-    // Update progress every 1% of games or every 5000 games, whichever is larger
-    let update_interval = std::cmp::max(5000, std::cmp::max(1, task.num_games / 100)) as u32;
-
-    for game_num in 0..task.num_games {
-        // Skip running simulation if all but one bot is disqualified
-        let active_count = permanently_disqualified.iter().filter(|&&x| !x).count();
-        if active_count <= 1 {
-            println!("Early termination: only {} active bot(s) remaining", active_count);
-            break;
-        }
-
-        let (results, usage, disqualified) = game::simulate_game(&mut strategies)?;
-        for i in 0..num_players {
-            total_stats[i].0 += results[i].0;
-            total_stats[i].1 += results[i].1;
-            total_usage_stats[i] = std::cmp::max(total_usage_stats[i], usage[i]);
-
-            // If a bot was disqualified in this game, mark it as permanently disqualified
-            if disqualified[i] {
-                if !permanently_disqualified[i] {
-                    println!("Bot {} (index {}) permanently disqualified due to memory limit", task.bots[i].name, i);
+/// Number of worker threads used when a simulation doesn't ask for a number
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Seed for a simulation that wasn't given one. Generated seeds are kept
+/// within 53 bits so they survive a round trip through JavaScript numbers in
+/// the frontend.
+pub fn random_seed() -> u64 {
+    rand::random::<u64>() >> 11
+}
+
+/// A game that was recorded for replay
+pub struct RecordedGame {
+    pub game_index: u32,
+    pub winner_index: Option<usize>,
+    pub events: Vec<GameEvent>,
+}
+
+/// Everything needed to play the games of one simulation.
+///
+/// The games are split into contiguous ranges (see `shard_games`), and a
+/// worker thread plays each range with its own instances of every strategy. An
+/// instance that starts at game `g` is seeded from the simulation seed and
+/// `g` alone, so a seeded run gives the same results whenever it is repeated
+/// with the same number of workers. With per-game isolation the number of
+/// workers doesn't affect the results at all.
+///
+/// Early stopping keeps that guarantee: each worker's range is divided into
+/// `CHECK_STAGES` equal stages, and a worker waits at the end of each stage
+/// until every worker has reached it and the rule has been checked against
+/// their combined totals. A run that stops early therefore always stops after
+/// the same games, regardless of how fast each worker happened to be.
+pub struct GamePlan<'a> {
+    pub engine: &'a Engine,
+    pub sources: &'a [StrategySource],
+    /// Player names for log messages
+    pub names: &'a [String],
+    pub num_games: u32,
+    pub seed: u64,
+    pub rules: &'a RuleSet,
+    pub isolation: Isolation,
+    pub recording: RecordingPolicy,
+    pub memory_limit: Option<u64>,
+    pub fuel_per_decision: Option<u64>,
+    /// Split between the workers in proportion to the games they play
+    pub fuel_per_simulation: Option<u64>,
+    /// Finishes the run through `SimulationControl::finish` once the results
+    /// are conclusive
+    pub early_stopping: Option<EarlyStopping>,
+    /// The paused run this one resumes, whose games the early stopping rule
+    /// counts along with the ones played here
+    pub resumed: Option<&'a Checkpoint>,
+    /// The games each worker plays
+    pub shards: Vec<Range<u32>>,
+}
+
+/// What `GamePlan::play` got through before it finished or was stopped
+pub struct PlayOutcome {
+    pub totals: Vec<ParticipantTotals>,
+    pub games_played: u32,
+    /// The games each worker had left, all empty unless the run was stopped
+    pub remaining: Vec<Range<u32>>,
+}
+
+impl GamePlan<'_> {
+    /// Plays every game, or until `control` asks to stop, and merges the
+    /// workers' totals. `record` receives each recorded game, and `progress`
+    /// is called periodically with the number of games finished so far and
+    /// the totals over those games.
+    pub fn play(
+        &self,
+        control: &SimulationControl,
+        record: impl Fn(RecordedGame) -> Result<()> + Sync,
+        mut progress: impl FnMut(u32, &[ParticipantTotals]),
+    ) -> Result<PlayOutcome> {
+        let stop = AtomicBool::new(false);
+        let stages = self
+            .early_stopping
+            .map(|_| CheckStages::new(self.shards.len(), self.sources.len()));
+        // Each worker's progress so far, updated after every game
+        let live: Vec<Mutex<ShardProgress>> = self
+            .shards
+            .iter()
+            .map(|_| Mutex::new(ShardProgress::new(self.sources.len())))
+            .collect();
+        let mut report = || {
+            let mut merged = ShardProgress::new(self.sources.len());
+            for shard in &live {
+                merged.add(&shard.lock().unwrap_or_else(PoisonError::into_inner));
+            }
+            progress(merged.games_played, &merged.totals);
+        };
+        // Workers enter the caller's runtime so WASI and database calls can block on it
+        let runtime = tokio::runtime::Handle::try_current().ok();
+
+        let shard_results = thread::scope(|scope| -> Result<Vec<_>> {
+            let mut workers = Vec::new();
+            for (worker, games) in self.shards.iter().cloned().enumerate() {
+                let (stop, record, runtime, live) = (&stop, &record, &runtime, &live[worker]);
+                let stages = stages.as_ref().map(|stages| (stages, worker));
+                let spawned = thread::Builder::new()
+                    .name(format!("sim-worker-{}", worker))
+                    .spawn_scoped(scope, move || {
+                        let _guard = runtime.as_ref().map(|runtime| runtime.enter());
+                        // Workers waiting at a stage only move on once told to stop
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            self.play_shard(games, live, stages, stop, control, record)
+                        }));
+                        if !matches!(result, Ok(Ok(_))) {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+                    });
+                match spawned {
+                    Ok(handle) => workers.push(handle),
+                    Err(e) => {
+                        stop.store(true, Ordering::Relaxed);
+                        return Err(e.into());
+                    }
+                }
+            }
+
+            while !workers.iter().all(|worker| worker.is_finished()) {
+                report();
+                match (&stages, &self.early_stopping) {
+                    (Some(stages), Some(early_stopping)) => {
+                        stages.check(&stop, control, |progress| {
+                            let (games_before, mut totals) = match self.resumed {
+                                Some(checkpoint) => {
+                                    (checkpoint.games_completed, checkpoint.totals.clone())
+                                }
+                                None => (0, vec![ParticipantTotals::default(); self.sources.len()]),
+                            };
+                            for (total, later) in totals.iter_mut().zip(&progress.totals) {
+                                total.add(later);
+                            }
+                            early_stopping.check(games_before + progress.games_played, &totals)
+                        })
+                    }
+                    _ => thread::sleep(PROGRESS_POLL_INTERVAL),
+                }
+            }
+            report();
+
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .map_err(|_| anyhow!("Simulation worker panicked"))?
+                })
+                .collect()
+        })?;
+
+        // Shards are in game order, so the first disqualification found is the earliest
+        let mut merged = ShardProgress::new(self.sources.len());
+        let mut remaining = Vec::new();
+        for (shard, shard_remaining) in shard_results {
+            merged.add(&shard);
+            remaining.push(shard_remaining);
+        }
+        Ok(PlayOutcome {
+            totals: merged.totals,
+            games_played: merged.games_played,
+            remaining,
+        })
+    }
+
+    /// Plays one worker's range of games, returning its progress and the games
+    /// it had left when it was stopped. The progress is also copied to `live`
+    /// after every game, and handed to `stages` at the end of each stage.
+    fn play_shard(
+        &self,
+        games: Range<u32>,
+        live: &Mutex<ShardProgress>,
+        stages: Option<(&CheckStages, usize)>,
+        stop: &AtomicBool,
+        control: &SimulationControl,
+        record: &(impl Fn(RecordedGame) -> Result<()> + Sync),
+    ) -> Result<(ShardProgress, Range<u32>)> {
+        let fuel_per_simulation = self
+            .fuel_per_simulation
+            .map(|budget| (budget as u128 * games.len() as u128 / self.num_games as u128) as u64);
+
+        let mut strategies = Vec::new();
+        for (index, source) in self.sources.iter().enumerate() {
+            let mut strategy = source.instantiate(self.engine, game::bot_seed(self.seed, index))?;
+            if let Some(limit) = self.memory_limit {
+                strategy.set_memory_limit(limit);
+            }
+            strategy.set_fuel_limits(self.fuel_per_decision, fuel_per_simulation);
+            if games.start > 0 {
+                strategy.reset(games.start as u64)?;
+            }
+            strategies.push(strategy);
+        }
+
+        let num_players = strategies.len();
+        let mut played = ShardProgress {
+            games_played: 0,
+            totals: strategies
+                .iter()
+                .map(|strategy| ParticipantTotals {
+                    disqualification: strategy.disqualification_reason(),
+                    money_products: vec![0.0; num_players],
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        // Track permanently disqualified bots
+        let mut permanently_disqualified: Vec<bool> = strategies
+            .iter()
+            .map(|strategy| strategy.is_disqualified())
+            .collect();
+        let mut remaining = games.end..games.end;
+
+        for game_num in games.clone() {
+            if let Some((stages, worker)) = stages {
+                stages.arrive(worker, &played, games.len() as u32, stop, control);
+            }
+            if stop.load(Ordering::Relaxed) || control.requested().is_some() {
+                remaining = game_num..games.end;
+                break;
+            }
+
+            // Skip the rest of the games if all but one bot is disqualified
+            let active_count = permanently_disqualified.iter().filter(|&&x| !x).count();
+            if active_count <= 1 {
+                println!(
+                    "Early termination at game {}: only {} active bot(s) remaining",
+                    game_num, active_count
+                );
+                break;
+            }
+
+            if game_num > games.start && self.isolation.resets_before(game_num as u64) {
+                for strategy in strategies.iter_mut() {
+                    strategy.reset(game_num as u64)?;
+                }
+            }
+
+            let mut rng = game::game_rng(self.seed, game_num as u64);
+            let mut recorder = if self.recording.should_record(game_num as u64) {
+                GameRecorder::enabled()
+            } else {
+                GameRecorder::disabled()
+            };
+            let (results, usage, disqualified) =
+                game::simulate_game(&mut strategies, self.rules, &mut rng, &mut recorder)?;
+
+            if recorder.is_enabled() {
+                record(RecordedGame {
+                    game_index: game_num,
+                    winner_index: results.iter().position(|(won, _)| *won > 0),
+                    events: recorder.into_events(),
+                })?;
+            }
+
+            for i in 0..num_players {
+                let total = &mut played.totals[i];
+                total.games_won += results[i].0;
+                total.total_money += results[i].1;
+                total.peak_memory_bytes = std::cmp::max(total.peak_memory_bytes, usage[i]);
+                total.fuel_consumed = strategies[i].fuel_consumed();
+                total.disqualification = strategies[i].disqualification_reason();
+                for (j, (_, money)) in results.iter().enumerate() {
+                    total.money_products[j] += results[i].1 as f64 * *money as f64;
+                }
+
+                // If a bot was disqualified in this game, mark it as permanently disqualified
+                if disqualified[i] && !permanently_disqualified[i] {
+                    let reason = strategies[i]
+                        .disqualification_reason()
+                        .map(|reason| reason.to_string())
+                        .unwrap_or_default();
+                    println!(
+                        "Bot {} (index {}) permanently disqualified at game {}: {}",
+                        self.names[i], i, game_num, reason
+                    );
                     permanently_disqualified[i] = true;
                 }
             }
+
+            played.games_played += 1;
+            live.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone_from(&played);
         }
 
-        // Update progress periodically using blocking database call
-        if (game_num + 1) % update_interval == 0 || game_num + 1 == task.num_games {
-            let pool_clone = pool.clone();
-            let sim_id = simulation_id.clone();
-            let games_done = game_num + 1;
+        if let Some((stages, worker)) = stages {
+            stages.finish(worker, &played);
+        }
+        Ok((played, remaining))
+    }
+}
+
+/// The games a worker has played and its totals over them
+#[derive(Clone)]
+struct ShardProgress {
+    games_played: u32,
+    totals: Vec<ParticipantTotals>,
+}
 
-            // Use the existing runtime handle instead of creating a new one
-            let handle = tokio::runtime::Handle::current();
-            handle.block_on(async {
-                let _ = sqlx::query("UPDATE simulations SET games_completed = ? WHERE id = ?")
-                    .bind(games_done)
-                    .bind(&sim_id)
-                    .execute(&pool_clone)
-                    .await;
-            });
+impl ShardProgress {
+    fn new(num_players: usize) -> Self {
+        ShardProgress {
+            games_played: 0,
+            totals: vec![ParticipantTotals::default(); num_players],
+        }
+    }
+
+    /// Adds the progress of a worker playing later games
+    fn add(&mut self, later: &ShardProgress) {
+        self.games_played += later.games_played;
+        for (total, player) in self.totals.iter_mut().zip(&later.totals) {
+            total.add(player);
         }
     }
+}
+
+/// Where the workers of a simulation with early stopping are in their stages
+/// (see `GamePlan`)
+struct CheckStages {
+    state: Mutex<StagesState>,
+    changed: Condvar,
+}
 
-    Ok((
-        total_stats,
-        total_usage_stats,
-        permanently_disqualified,
-        (task.simulation_id, bot_ids),
-    ))
+struct StagesState {
+    workers: Vec<WorkerStage>,
+    /// Stages the rule has been checked at without stopping the run
+    cleared: u64,
+}
+
+struct WorkerStage {
+    /// The last stage this worker has reached
+    reached: u64,
+    /// The worker's progress at that stage, or over all its games once done
+    progress: ShardProgress,
+    /// Whether the worker has stopped playing
+    done: bool,
+}
+
+impl CheckStages {
+    fn new(workers: usize, num_players: usize) -> Self {
+        CheckStages {
+            state: Mutex::new(StagesState {
+                workers: (0..workers)
+                    .map(|_| WorkerStage {
+                        reached: 0,
+                        progress: ShardProgress::new(num_players),
+                        done: false,
+                    })
+                    .collect(),
+                cleared: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StagesState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Called by a worker before each game. If `played` ends a stage of its
+    /// `shard_len` games, waits until the stage is cleared or the run is
+    /// stopped.
+    fn arrive(
+        &self,
+        worker: usize,
+        played: &ShardProgress,
+        shard_len: u32,
+        stop: &AtomicBool,
+        control: &SimulationControl,
+    ) {
+        let mut state = self.lock();
+        loop {
+            // Small ranges end several stages after the same game
+            let stage = state.workers[worker].reached + 1;
+            if stage >= CHECK_STAGES
+                || (shard_len as u64 * stage / CHECK_STAGES) as u32 > played.games_played
+            {
+                return;
+            }
+            let reached = &mut state.workers[worker];
+            reached.reached = stage;
+            reached.progress.clone_from(played);
+            self.changed.notify_all();
+
+            while state.cleared < stage
+                && !stop.load(Ordering::Relaxed)
+                && control.requested().is_none()
+            {
+                state = self
+                    .changed
+                    .wait_timeout(state, PROGRESS_POLL_INTERVAL)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            }
+            if state.cleared < stage {
+                return;
+            }
+        }
+    }
+
+    /// Called by a worker once it stops playing, with its progress over all
+    /// the games it played
+    fn finish(&self, worker: usize, played: &ShardProgress) {
+        let mut state = self.lock();
+        let finished = &mut state.workers[worker];
+        finished.progress.clone_from(played);
+        finished.done = true;
+        self.changed.notify_all();
+    }
+
+    /// Checks `rule` at every stage all workers have reached, finishing the
+    /// run through `control` when it gives a reason to stop, then waits up to
+    /// `PROGRESS_POLL_INTERVAL` for the workers to move on
+    fn check(
+        &self,
+        stop: &AtomicBool,
+        control: &SimulationControl,
+        rule: impl Fn(&ShardProgress) -> Option<String>,
+    ) {
+        let mut state = self.lock();
+        while !stop.load(Ordering::Relaxed) && control.requested().is_none() {
+            let stage = state.cleared + 1;
+            let reached = state
+                .workers
+                .iter()
+                .all(|worker| worker.done || worker.reached >= stage);
+            let playing = state.workers.iter().any(|worker| !worker.done);
+            if stage >= CHECK_STAGES || !reached || !playing {
+                break;
+            }
+
+            // Workers are in game order, as `ShardProgress::add` expects
+            let mut merged = ShardProgress::new(state.workers[0].progress.totals.len());
+            for worker in &state.workers {
+                merged.add(&worker.progress);
+            }
+            match rule(&merged) {
+                Some(reason) => control.finish(reason),
+                None => state.cleared = stage,
+            }
+            self.changed.notify_all();
+        }
+        drop(
+            self.changed
+                .wait_timeout(state, PROGRESS_POLL_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+}
+
+/// Splits games `0..num_games` into at most `workers` contiguous, non-empty
+/// ranges of near-equal size
+pub fn shard_games(num_games: u32, workers: usize) -> Vec<Range<u32>> {
+    let shards = workers.clamp(1, num_games.max(1) as usize) as u32;
+    let (base, extra) = (num_games / shards, num_games % shards);
+    let mut start = 0;
+    (0..shards)
+        .map(|shard| {
+            let end = start + base + u32::from(shard < extra);
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each bot's (wins, money) and the recorded games in order
+    type Run = (Vec<(u32, i64)>, Vec<(u32, Vec<GameEvent>)>);
+
+    /// Plays 200 seeded games between the hold-at reference bots
+    fn play(workers: usize) -> Run {
+        let sources: Vec<StrategySource> = ["hold-at-15", "hold-at-20", "hold-at-25"]
+            .iter()
+            .map(|name| StrategySource::Builtin(name.to_string()))
+            .collect();
+        let names: Vec<String> = (0..sources.len()).map(|i| format!("bot{}", i)).collect();
+        let plan = GamePlan {
+            engine: &Engine::default(),
+            sources: &sources,
+            names: &names,
+            num_games: 200,
+            seed: 42,
+            rules: &RuleSet::default(),
+            isolation: Isolation::Persistent,
+            recording: RecordingPolicy::Sample { every: 10 },
+            memory_limit: None,
+            fuel_per_decision: None,
+            fuel_per_simulation: None,
+            early_stopping: None,
+            resumed: None,
+            shards: shard_games(200, workers),
+        };
+        let recorded = Mutex::new(Vec::new());
+        let outcome = plan
+            .play(
+                &SimulationControl::default(),
+                |game| {
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push((game.game_index, game.events));
+                    Ok(())
+                },
+                |_, _| {},
+            )
+            .unwrap();
+        assert_eq!(outcome.games_played, 200);
+
+        let mut recorded = recorded.into_inner().unwrap();
+        recorded.sort_by_key(|(game_index, _)| *game_index);
+        let totals = outcome
+            .totals
+            .iter()
+            .map(|total| (total.games_won, total.total_money))
+            .collect();
+        (totals, recorded)
+    }
+
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        let (totals, recorded) = play(1);
+        assert_eq!(totals.iter().map(|(won, _)| won).sum::<u32>(), 200);
+        assert_eq!(totals.iter().map(|(_, money)| money).sum::<i64>(), 0);
+        assert_eq!(recorded.len(), 20);
+        assert_eq!(play(1), (totals, recorded));
+    }
+
+    #[test]
+    fn totals_do_not_depend_on_the_worker_count() {
+        let single = play(1);
+        assert_eq!(play(3), single);
+        assert_eq!(play(8), single);
+    }
+
+    #[test]
+    fn shards_cover_every_game_once() {
+        assert_eq!(shard_games(10, 3), vec![0..4, 4..7, 7..10]);
+        assert_eq!(shard_games(2, 8), vec![0..1, 1..2]);
+        assert_eq!(shard_games(5, 0), vec![0..5]);
+        assert_eq!(shard_games(0, 4), vec![0..0]);
+    }
 }
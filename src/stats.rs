@@ -0,0 +1,446 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::simulation::ParticipantTotals;
+
+/// z-score of a two-sided 95% confidence interval under the normal approximation
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Significance level of the pairwise tests
+pub const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// Mean of a per-game quantity with its uncertainty
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Estimate {
+    pub mean: f64,
+    pub standard_deviation: f64,
+    pub standard_error: f64,
+    /// 95% confidence interval for the mean, `[low, high]`
+    pub ci95: [f64; 2],
+}
+
+impl Estimate {
+    /// Estimates the mean from the sum and sum of squares of `n` samples.
+    /// `None` with fewer than two samples.
+    pub fn from_sums(n: u64, sum: f64, sum_squares: f64) -> Option<Estimate> {
+        if n < 2 {
+            return None;
+        }
+        let n = n as f64;
+        let mean = sum / n;
+        let variance = ((sum_squares - sum * mean) / (n - 1.0)).max(0.0);
+        let standard_deviation = variance.sqrt();
+        let standard_error = standard_deviation / n.sqrt();
+        Some(Estimate {
+            mean,
+            standard_deviation,
+            standard_error,
+            ci95: [mean - Z_95 * standard_error, mean + Z_95 * standard_error],
+        })
+    }
+}
+
+/// Whether one bot makes more money per game than another over the same games
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub player_a: usize,
+    pub player_b: usize,
+    /// Per-game money of A minus that of B
+    pub difference: Estimate,
+    pub z_score: f64,
+    /// Two-sided p-value for "A and B make the same money on average"
+    pub p_value: f64,
+    /// Whether the difference is significant at the 5% level
+    pub significant: bool,
+    /// Player index of the significantly better bot, if either is
+    pub better: Option<usize>,
+}
+
+/// Paired test of the per-game money of two bots that sat at the same table
+/// for `n` games, from the sums of their money, the sums of its squares and
+/// the sum of the products of their money in each game. `None` with fewer
+/// than two games, or when the difference is the same in every game and the
+/// test isn't defined.
+pub fn compare(
+    (player_a, sum_a, squares_a): (usize, f64, f64),
+    (player_b, sum_b, squares_b): (usize, f64, f64),
+    products: f64,
+    n: u64,
+) -> Option<Comparison> {
+    // (a - b)^2 = a^2 - 2ab + b^2, so the differences' sums follow from the bots'
+    let difference = Estimate::from_sums(n, sum_a - sum_b, squares_a - 2.0 * products + squares_b)?;
+    if difference.standard_error == 0.0 {
+        return None;
+    }
+    let z_score = difference.mean / difference.standard_error;
+    let p_value = erfc(z_score.abs() / std::f64::consts::SQRT_2);
+    let significant = p_value < SIGNIFICANCE_LEVEL;
+    let better = significant.then_some(if difference.mean > 0.0 {
+        player_a
+    } else {
+        player_b
+    });
+    Some(Comparison {
+        player_a,
+        player_b,
+        difference,
+        z_score,
+        p_value,
+        significant,
+        better,
+    })
+}
+
+/// Complementary error function, accurate to about 1.2e-7 (Numerical Recipes' `erfcc`)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+/// Stops a simulation once the games played so far are enough to answer the
+/// question it was run for. The rule is checked at fixed points in every
+/// worker's games, about every 1% of games, so where a seeded run stops
+/// doesn't depend on timing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EarlyStopping {
+    #[serde(flatten)]
+    pub rule: StoppingRule,
+    /// Games played before the rule is first checked, so the variances it
+    /// relies on are estimated from enough games
+    #[serde(default = "default_min_games")]
+    pub min_games: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum StoppingRule {
+    /// Stop once every bot's 95% confidence interval for money per game is at
+    /// most `width` dollars wide
+    CiWidth { width: f64 },
+    /// Wald's sequential probability ratio test on every pair of bots: stop
+    /// once each pair is shown to differ by `effect` dollars per game in
+    /// either direction, or not to. `alpha` is the chance of calling a pair
+    /// different when it isn't, `beta` of missing a difference of `effect`.
+    Sprt {
+        effect: f64,
+        #[serde(default = "default_error_rate")]
+        alpha: f64,
+        #[serde(default = "default_error_rate")]
+        beta: f64,
+    },
+}
+
+fn default_min_games() -> u32 {
+    1000
+}
+
+fn default_error_rate() -> f64 {
+    0.05
+}
+
+impl EarlyStopping {
+    pub fn validate(&self) -> Result<()> {
+        match self.rule {
+            StoppingRule::CiWidth { width } => {
+                if width.is_nan() || width <= 0.0 {
+                    bail!("confidence interval width must be greater than 0");
+                }
+            }
+            StoppingRule::Sprt {
+                effect,
+                alpha,
+                beta,
+            } => {
+                if effect.is_nan() || effect <= 0.0 {
+                    bail!("SPRT effect must be greater than 0");
+                }
+                if !(alpha > 0.0 && alpha < 1.0 && beta > 0.0 && beta < 1.0) {
+                    bail!("SPRT error rates must be between 0 and 1");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Why the simulation can stop after `games` games with these totals, or
+    /// `None` to keep playing
+    pub fn check(&self, games: u32, totals: &[ParticipantTotals]) -> Option<String> {
+        if games < self.min_games.max(2) {
+            return None;
+        }
+        let n = games as u64;
+        // Sum and sum of squares of a bot's money per game
+        let money = |i: usize| {
+            let total = &totals[i];
+            Some((
+                total.total_money as f64,
+                total.money_products.get(i).copied()?,
+            ))
+        };
+
+        match self.rule {
+            StoppingRule::CiWidth { width } => {
+                for i in 0..totals.len() {
+                    let (sum, squares) = money(i)?;
+                    let estimate = Estimate::from_sums(n, sum, squares)?;
+                    if estimate.ci95[1] - estimate.ci95[0] > width {
+                        return None;
+                    }
+                }
+                Some(format!(
+                    "ci_width: every bot's 95% confidence interval is narrower than ${} after {} games",
+                    width, games
+                ))
+            }
+            StoppingRule::Sprt {
+                effect,
+                alpha,
+                beta,
+            } => {
+                // Each direction is tested at half the significance level
+                let upper = ((1.0 - beta) / (alpha / 2.0)).ln();
+                let lower = (beta / (1.0 - alpha / 2.0)).ln();
+                let mut differences = 0;
+                for a in 0..totals.len() {
+                    for b in a + 1..totals.len() {
+                        let (sum_a, squares_a) = money(a)?;
+                        let (sum_b, squares_b) = money(b)?;
+                        let products = totals[a].money_products.get(b).copied()?;
+                        let sum = sum_a - sum_b;
+                        let difference =
+                            Estimate::from_sums(n, sum, squares_a - 2.0 * products + squares_b)?;
+                        let variance = difference.standard_deviation.powi(2);
+                        if variance == 0.0 {
+                            // Identical money in every game: no difference at all
+                            continue;
+                        }
+
+                        // Log-likelihood ratios of a difference of +effect and
+                        // of -effect against none, for normally distributed
+                        // per-game differences
+                        let ratio =
+                            |mean: f64| (mean * sum - n as f64 * mean * mean / 2.0) / variance;
+                        let (better_a, better_b) = (ratio(effect), ratio(-effect));
+                        if better_a >= upper || better_b >= upper {
+                            differences += 1;
+                        } else if better_a > lower || better_b > lower {
+                            return None;
+                        }
+                    }
+                }
+                Some(format!(
+                    "sprt: every pair of bots decided after {} games, {} differing by at least ${} per game",
+                    games, differences, effect
+                ))
+            }
+        }
+    }
+}
+
+impl FromStr for EarlyStopping {
+    type Err = anyhow::Error;
+
+    /// Parses `ci-width:<dollars>` or `sprt:<dollars>`
+    fn from_str(spec: &str) -> Result<Self> {
+        let (mode, value) = spec.split_once(':').with_context(|| {
+            format!(
+                "Invalid early stopping '{}', expected ci-width:<dollars> or sprt:<dollars>",
+                spec
+            )
+        })?;
+        let value: f64 = value
+            .parse()
+            .with_context(|| format!("Invalid early stopping value: {}", value))?;
+        let rule = match mode {
+            "ci-width" => StoppingRule::CiWidth { width: value },
+            "sprt" => StoppingRule::Sprt {
+                effect: value,
+                alpha: default_error_rate(),
+                beta: default_error_rate(),
+            },
+            _ => bail!(
+                "Invalid early stopping '{}', expected ci-width:<dollars> or sprt:<dollars>",
+                spec
+            ),
+        };
+        let early_stopping = EarlyStopping {
+            rule,
+            min_games: default_min_games(),
+        };
+        early_stopping.validate()?;
+        Ok(early_stopping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn estimate_of_small_sample() {
+        // 1, 2, 3, 4: mean 2.5, sample variance 5/3
+        let estimate = Estimate::from_sums(4, 10.0, 30.0).unwrap();
+        assert_close(estimate.mean, 2.5, 1e-12);
+        assert_close(estimate.standard_deviation, (5.0f64 / 3.0).sqrt(), 1e-12);
+        assert_close(estimate.standard_error, 0.645_497_224, 1e-9);
+        assert_close(estimate.ci95[0], 1.234_849, 1e-6);
+        assert_close(estimate.ci95[1], 3.765_151, 1e-6);
+    }
+
+    #[test]
+    fn estimate_needs_two_samples() {
+        assert!(Estimate::from_sums(0, 0.0, 0.0).is_none());
+        assert!(Estimate::from_sums(1, 5.0, 25.0).is_none());
+    }
+
+    #[test]
+    fn estimate_of_constant_sample_has_zero_width() {
+        let estimate = Estimate::from_sums(3, 6.0, 12.0).unwrap();
+        assert_close(estimate.standard_deviation, 0.0, 1e-12);
+        assert_eq!(estimate.ci95, [2.0, 2.0]);
+    }
+
+    #[test]
+    fn erfc_known_values() {
+        assert_close(erfc(0.0), 1.0, 1.2e-7);
+        // Two-sided p-value at z = 1.96
+        assert_close(erfc(1.96 / std::f64::consts::SQRT_2), 0.05, 1e-4);
+        assert_close(erfc(-1.0), 2.0 - erfc(1.0), 1e-12);
+        assert_close(erfc(1.0), 0.157_299_207, 1.2e-7);
+    }
+
+    #[test]
+    fn compare_finds_better_bot() {
+        // A won 1, 2, 3, 4 while B won nothing
+        let comparison = compare((0, 10.0, 30.0), (1, 0.0, 0.0), 0.0, 4).unwrap();
+        assert_close(comparison.difference.mean, 2.5, 1e-12);
+        assert_close(comparison.z_score, 2.5 / 0.645_497_224, 1e-6);
+        assert!(comparison.p_value < SIGNIFICANCE_LEVEL);
+        assert!(comparison.significant);
+        assert_eq!(comparison.better, Some(0));
+    }
+
+    #[test]
+    fn compare_is_symmetric() {
+        // A: 1, -1, 2, 0 and B: 0, 1, 1, -1, so A - B is 1, -2, 1, 1
+        let a = (0, 2.0, 6.0);
+        let b = (1, 1.0, 3.0);
+        let products = 0.0 - 1.0 + 2.0 + 0.0;
+        let ab = compare(a, b, products, 4).unwrap();
+        let ba = compare(b, a, products, 4).unwrap();
+        assert_close(ab.difference.mean, 0.25, 1e-12);
+        assert_close(ab.z_score, -ba.z_score, 1e-12);
+        assert_close(ab.p_value, ba.p_value, 1e-12);
+        assert!(!ab.significant);
+        assert_eq!(ab.better, None);
+    }
+
+    #[test]
+    fn compare_needs_two_games_and_some_variance() {
+        assert!(compare((0, 1.0, 1.0), (1, 0.0, 0.0), 0.0, 1).is_none());
+        // Identical money in every game
+        assert!(compare((0, 6.0, 12.0), (1, 6.0, 12.0), 12.0, 3).is_none());
+    }
+
+    /// Totals of two bots over `n` games, where the first wins `mean ± spread`
+    /// on alternate games and the second wins nothing
+    fn totals(n: u32, mean: f64, spread: f64) -> Vec<ParticipantTotals> {
+        let (mut sum, mut squares) = (0.0, 0.0);
+        for game in 0..n {
+            let money = if game % 2 == 0 {
+                mean + spread
+            } else {
+                mean - spread
+            };
+            sum += money;
+            squares += money * money;
+        }
+        vec![
+            ParticipantTotals {
+                total_money: sum as i64,
+                money_products: vec![squares, 0.0],
+                ..Default::default()
+            },
+            ParticipantTotals {
+                money_products: vec![0.0, 0.0],
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn sprt(effect: f64, min_games: u32) -> EarlyStopping {
+        EarlyStopping {
+            rule: StoppingRule::Sprt {
+                effect,
+                alpha: 0.05,
+                beta: 0.05,
+            },
+            min_games,
+        }
+    }
+
+    fn ci_width(width: f64, min_games: u32) -> EarlyStopping {
+        EarlyStopping {
+            rule: StoppingRule::CiWidth { width },
+            min_games,
+        }
+    }
+
+    #[test]
+    fn sprt_stops_on_clear_difference() {
+        let reason = sprt(5.0, 100).check(1000, &totals(1000, 20.0, 5.0));
+        assert!(reason.unwrap().starts_with("sprt:"));
+    }
+
+    #[test]
+    fn sprt_continues_while_undecided() {
+        // Halfway between no difference and `effect`, with a lot of noise
+        assert_eq!(sprt(5.0, 100).check(1000, &totals(1000, 2.5, 100.0)), None);
+    }
+
+    #[test]
+    fn ci_width_stops_once_narrow() {
+        let reason = ci_width(2.0, 100).check(1000, &totals(1000, 20.0, 5.0));
+        assert!(reason.unwrap().starts_with("ci_width:"));
+    }
+
+    #[test]
+    fn ci_width_continues_while_wide() {
+        assert_eq!(
+            ci_width(2.0, 100).check(1000, &totals(1000, 2.5, 100.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn nothing_stops_before_min_games() {
+        let separated = totals(1000, 20.0, 5.0);
+        assert_eq!(sprt(5.0, 5000).check(1000, &separated), None);
+        assert_eq!(ci_width(2.0, 5000).check(1000, &separated), None);
+        assert!(sprt(5.0, 1000).check(1000, &separated).is_some());
+        // At least two games are needed for a variance, whatever `min_games` says
+        assert_eq!(sprt(5.0, 0).check(1, &totals(1, 20.0, 0.0)), None);
+    }
+}
@@ -0,0 +1,352 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use wasmtime::component::Component;
+use wasmtime::Engine;
+
+use crate::cache::ComponentCache;
+use crate::game::{GameState, TurnHistoryEntry, TurnOutcome};
+use crate::wasm::{self, WasmStrategy};
+
+/// Prefix of bot file paths that name a native strategy instead of a WASM file
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+/// Native reference bots registered alongside uploaded bots: (builtin name, description)
+pub const REFERENCE_BOTS: &[(&str, &str)] = &[
+    (
+        "hold-at-15",
+        "Built-in reference bot: holds after 15 turn points, chases the leader in the endgame",
+    ),
+    (
+        "hold-at-20",
+        "Built-in reference bot: holds after 20 turn points, chases the leader in the endgame",
+    ),
+    (
+        "hold-at-25",
+        "Built-in reference bot: holds after 25 turn points, chases the leader in the endgame",
+    ),
+];
+
+/// Why a strategy was removed from a simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisqualificationReason {
+    /// Tried to grow its memory past the limit
+    MemoryLimit,
+    /// Ran out of CPU budget (wasmtime fuel) during a decision or the run
+    FuelExhausted,
+    /// Hit a WebAssembly trap: a panic, `unreachable`, out-of-bounds access,
+    /// division by zero and the like
+    Trap,
+    /// The call failed without trapping: a result that couldn't be lifted
+    /// into a decision, the component exiting or any other error from the
+    /// guest that isn't a host-side failure
+    InvalidOutput,
+}
+
+impl DisqualificationReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisqualificationReason::MemoryLimit => "memory_limit",
+            DisqualificationReason::FuelExhausted => "fuel_exhausted",
+            DisqualificationReason::Trap => "trap",
+            DisqualificationReason::InvalidOutput => "invalid_output",
+        }
+    }
+}
+
+impl FromStr for DisqualificationReason {
+    type Err = anyhow::Error;
+
+    fn from_str(reason: &str) -> Result<Self> {
+        match reason {
+            "memory_limit" => Ok(DisqualificationReason::MemoryLimit),
+            "fuel_exhausted" => Ok(DisqualificationReason::FuelExhausted),
+            "trap" => Ok(DisqualificationReason::Trap),
+            "invalid_output" => Ok(DisqualificationReason::InvalidOutput),
+            _ => bail!("Unknown disqualification reason: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for DisqualificationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How long a strategy instance lives during a simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Isolation {
+    /// One instance plays every game, so state carries over between games
+    #[default]
+    Persistent,
+    /// A fresh instance for every game
+    PerGame,
+    /// A fresh instance every `games` games
+    Every { games: u32 },
+}
+
+impl Isolation {
+    /// Whether strategies start over from a fresh instance before `game_index`
+    pub fn resets_before(&self, game_index: u64) -> bool {
+        match *self {
+            Isolation::Persistent => false,
+            Isolation::PerGame => game_index > 0,
+            Isolation::Every { games } => {
+                game_index > 0 && games > 0 && game_index.is_multiple_of(games as u64)
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Isolation::Every { games: 0 } = self {
+            bail!("isolation interval must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Isolation {
+    type Err = anyhow::Error;
+
+    /// Parses `persistent`, `per-game` or `every:<games>`
+    fn from_str(spec: &str) -> Result<Self> {
+        let isolation = match spec {
+            "persistent" => Isolation::Persistent,
+            "per-game" => Isolation::PerGame,
+            _ => match spec.strip_prefix("every:") {
+                Some(games) => Isolation::Every {
+                    games: games
+                        .parse()
+                        .with_context(|| format!("Invalid isolation interval: {}", games))?,
+                },
+                None => bail!(
+                    "Invalid isolation '{}', expected persistent, per-game or every:<games>",
+                    spec
+                ),
+            },
+        };
+        isolation.validate()?;
+        Ok(isolation)
+    }
+}
+
+/// A player the game engine can ask for roll/hold decisions.
+///
+/// `WasmStrategy` runs uploaded components; native strategies implement the
+/// trait directly and can sit at the same table.
+pub trait Strategy: Send {
+    /// Decides whether to roll again (`true`) or hold (`false`)
+    fn should_roll(&mut self, state: &GameState) -> Result<bool>;
+
+    /// Called before every game with the table size and this player's seat
+    /// (0 = first to roll)
+    fn on_game_start(&mut self, _num_players: usize, _seat: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after every turn at the table, not just this player's own, with
+    /// every roll of the game so far
+    fn on_turn_end(
+        &mut self,
+        _player: usize,
+        _outcome: TurnOutcome,
+        _turn_history: &[TurnHistoryEntry],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once a game is over with each player's final score, the money
+    /// they won or lost and every roll of the game
+    fn on_game_end(
+        &mut self,
+        _final_scores: &[u32],
+        _payouts: &[i64],
+        _turn_history: &[TurnHistoryEntry],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Starts over from a freshly created instance for the games from
+    /// `game_index` on, forgetting any state built up in earlier games. Limits,
+    /// usage totals and disqualification carry over.
+    fn reset(&mut self, _game_index: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// Highest memory use seen so far, in bytes
+    fn peak_memory_bytes(&self) -> u64 {
+        0
+    }
+
+    /// Caps the memory the strategy may use; strategies that can't be limited ignore it
+    fn set_memory_limit(&mut self, _limit_bytes: u64) {}
+
+    /// Sets the CPU budget per decision and for the whole run; `None` is unlimited.
+    /// Strategies that can't be metered ignore it.
+    fn set_fuel_limits(&mut self, _per_decision: Option<u64>, _per_simulation: Option<u64>) {}
+
+    /// CPU used across all decisions so far, in wasmtime fuel units
+    fn fuel_consumed(&self) -> u64 {
+        0
+    }
+
+    /// Why the strategy must sit out the rest of the run, if it broke a limit
+    fn disqualification_reason(&self) -> Option<DisqualificationReason> {
+        None
+    }
+
+    fn is_disqualified(&self) -> bool {
+        self.disqualification_reason().is_some()
+    }
+}
+
+/// Holds once a turn has earned `threshold` points, but keeps rolling in the
+/// endgame until it has overtaken the leader.
+pub struct HoldAt {
+    pub threshold: u32,
+}
+
+impl Strategy for HoldAt {
+    fn should_roll(&mut self, state: &GameState) -> Result<bool> {
+        let turn_points = state
+            .current_total_score
+            .saturating_sub(state.current_banked_score);
+
+        let max_opponent = state
+            .all_players_banked_scores
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != state.current_player_index as usize)
+            .map(|(_, &score)| score)
+            .max()
+            .unwrap_or(0);
+
+        if state.rules.reaches_target(max_opponent) {
+            return Ok(state.current_total_score <= max_opponent);
+        }
+
+        Ok(turn_points < self.threshold)
+    }
+}
+
+/// Plays back a fixed sequence of decisions, starting over once it runs out.
+/// Useful for test bots whose every move is known in advance.
+pub struct Scripted {
+    decisions: Vec<bool>,
+    next: usize,
+}
+
+impl Scripted {
+    pub fn new(decisions: Vec<bool>) -> Self {
+        Scripted { decisions, next: 0 }
+    }
+
+    /// Parses a script of `r` (roll) and `h` (hold) characters, e.g. `rrh`.
+    pub fn parse(script: &str) -> Result<Self> {
+        let decisions = script
+            .chars()
+            .map(|c| match c {
+                'r' => Ok(true),
+                'h' => Ok(false),
+                other => bail!("Invalid script character '{}', expected 'r' or 'h'", other),
+            })
+            .collect::<Result<Vec<bool>>>()?;
+        if decisions.is_empty() {
+            bail!("Script must contain at least one decision");
+        }
+        Ok(Scripted::new(decisions))
+    }
+}
+
+impl Strategy for Scripted {
+    fn should_roll(&mut self, _state: &GameState) -> Result<bool> {
+        let decision = self.decisions[self.next];
+        self.next = (self.next + 1) % self.decisions.len();
+        Ok(decision)
+    }
+
+    fn reset(&mut self, _game_index: u64) -> Result<()> {
+        self.next = 0;
+        Ok(())
+    }
+}
+
+/// Sits out every game, for a bot that is already disqualified
+pub struct Disqualified(pub DisqualificationReason);
+
+impl Strategy for Disqualified {
+    fn should_roll(&mut self, _state: &GameState) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn disqualification_reason(&self) -> Option<DisqualificationReason> {
+        Some(self.0)
+    }
+}
+
+/// Creates a native strategy from its builtin name (without the prefix):
+/// `hold-at-<points>` or `script:<r|h...>`.
+pub fn builtin(name: &str) -> Result<Box<dyn Strategy>> {
+    if let Some(threshold) = name.strip_prefix("hold-at-") {
+        let threshold = threshold
+            .parse()
+            .with_context(|| format!("Invalid hold threshold in builtin strategy: {}", name))?;
+        return Ok(Box::new(HoldAt { threshold }));
+    }
+
+    if let Some(script) = name.strip_prefix("script:") {
+        return Ok(Box::new(Scripted::parse(script)?));
+    }
+
+    bail!("Unknown builtin strategy: {}", name)
+}
+
+/// A bot's strategy, loaded once and instantiated for every worker that
+/// plays it.
+#[derive(Clone)]
+pub enum StrategySource {
+    Builtin(String),
+    Wasm(Component),
+    /// A bot that was disqualified before a paused simulation was resumed
+    Disqualified(DisqualificationReason),
+}
+
+impl StrategySource {
+    /// Loads the strategy a bot's `file_path` points at: a native strategy for
+    /// `builtin:` paths, otherwise a WASM component (compiled through `cache`
+    /// when given).
+    pub fn load(
+        engine: &Engine,
+        cache: Option<&ComponentCache>,
+        file_path: &str,
+    ) -> Result<StrategySource> {
+        match file_path.strip_prefix(BUILTIN_PREFIX) {
+            Some(name) => {
+                builtin(name)?;
+                Ok(StrategySource::Builtin(name.to_string()))
+            }
+            None => Ok(StrategySource::Wasm(wasm::load_component(
+                engine, cache, file_path,
+            )?)),
+        }
+    }
+
+    /// Creates a fresh instance whose randomness is seeded from `seed`. A WASM
+    /// bot that traps or breaks a limit while starting up sits out as `Disqualified`.
+    pub fn instantiate(&self, engine: &Engine, seed: u64) -> Result<Box<dyn Strategy>> {
+        match self {
+            StrategySource::Builtin(name) => builtin(name),
+            StrategySource::Wasm(component) => {
+                match WasmStrategy::start(engine, component, seed)? {
+                    Ok(strategy) => Ok(Box::new(strategy)),
+                    Err(reason) => Ok(Box::new(Disqualified(reason))),
+                }
+            }
+            StrategySource::Disqualified(reason) => Ok(Box::new(Disqualified(*reason))),
+        }
+    }
+}
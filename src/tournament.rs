@@ -0,0 +1,1251 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::sync::{broadcast::error::RecvError, Mutex};
+use uuid::Uuid;
+
+use crate::{
+    db,
+    simulation::{SimulationManager, SimulationSettings},
+};
+
+/// Most simulations a single tournament may schedule
+pub const MAX_MATCHES: usize = 1000;
+
+/// Scheduling reads a tournament's matches and then adds to them, so it runs
+/// one tournament round at a time
+static SCHEDULE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// How a tournament decides which bots meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentFormat {
+    /// One match for every set of `table_size` bots
+    #[default]
+    RoundRobin,
+    /// A fixed number of rounds, each pairing bots with similar records
+    Swiss,
+    /// A knockout bracket: losers are out, winners meet in the next round
+    SingleElimination,
+}
+
+impl TournamentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TournamentFormat::RoundRobin => "round_robin",
+            TournamentFormat::Swiss => "swiss",
+            TournamentFormat::SingleElimination => "single_elimination",
+        }
+    }
+}
+
+impl FromStr for TournamentFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        match format {
+            "round_robin" => Ok(TournamentFormat::RoundRobin),
+            "swiss" => Ok(TournamentFormat::Swiss),
+            "single_elimination" => Ok(TournamentFormat::SingleElimination),
+            _ => bail!(
+                "Invalid tournament format '{}', expected round_robin, swiss or single_elimination",
+                format
+            ),
+        }
+    }
+}
+
+/// What to create a tournament with, apart from its bots
+pub struct TournamentPlan {
+    pub name: Option<String>,
+    pub format: TournamentFormat,
+    /// Bots at the table in each match; always two for Swiss and knockouts
+    pub table_size: usize,
+    /// Rounds a Swiss tournament plays; enough to separate the field when `None`
+    pub rounds: Option<usize>,
+    /// How every match is run; match `i` is played with this seed plus `i`
+    pub settings: SimulationSettings,
+}
+
+impl TournamentPlan {
+    pub fn validate(&self, bot_ids: &[String]) -> Result<()> {
+        let entrants = bot_ids.len();
+        if bot_ids.iter().collect::<HashSet<_>>().len() != entrants {
+            bail!("a bot can only enter a tournament once");
+        }
+        if self.table_size < 2 || entrants < self.table_size {
+            bail!("a tournament needs at least as many bots as sit at a table, and two at least");
+        }
+        if self.format != TournamentFormat::RoundRobin && self.table_size != 2 {
+            bail!("Swiss and knockout matches are between two bots");
+        }
+        if self.rounds.is_some() && self.format != TournamentFormat::Swiss {
+            bail!("only Swiss tournaments take a number of rounds");
+        }
+        let matches = match self.format {
+            TournamentFormat::RoundRobin => count_combinations(entrants, self.table_size),
+            TournamentFormat::Swiss => {
+                let rounds = self
+                    .rounds
+                    .unwrap_or_else(|| default_swiss_rounds(entrants));
+                if rounds == 0 || rounds >= entrants {
+                    bail!(
+                        "a Swiss tournament of {} bots plays 1 to {} rounds",
+                        entrants,
+                        entrants - 1
+                    );
+                }
+                rounds.saturating_mul(entrants / 2)
+            }
+            TournamentFormat::SingleElimination => entrants - 1,
+        };
+        if matches > MAX_MATCHES {
+            bail!(
+                "tournament would play {} matches, at most {} are allowed",
+                matches,
+                MAX_MATCHES
+            );
+        }
+        Ok(())
+    }
+
+    /// Rounds the tournament is played over
+    fn planned_rounds(&self, entrants: usize) -> usize {
+        match self.format {
+            TournamentFormat::RoundRobin => 1,
+            TournamentFormat::Swiss => self
+                .rounds
+                .unwrap_or_else(|| default_swiss_rounds(entrants)),
+            TournamentFormat::SingleElimination => {
+                entrants.next_power_of_two().trailing_zeros() as usize
+            }
+        }
+    }
+}
+
+/// Enough Swiss rounds for one bot to be left with a perfect record
+pub fn default_swiss_rounds(entrants: usize) -> usize {
+    (entrants.next_power_of_two().trailing_zeros() as usize)
+        .max(1)
+        .min(entrants.saturating_sub(1))
+}
+
+/// Every `k`-element subset of `0..n`, in lexicographic order
+pub fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut subsets = Vec::new();
+    if k == 0 || k > n {
+        return subsets;
+    }
+    let mut subset: Vec<usize> = (0..k).collect();
+    loop {
+        subsets.push(subset.clone());
+        // Advance the rightmost element that still has room to move
+        let Some(i) = (0..k).rev().find(|&i| subset[i] < n - k + i) else {
+            return subsets;
+        };
+        subset[i] += 1;
+        for j in i + 1..k {
+            subset[j] = subset[j - 1] + 1;
+        }
+    }
+}
+
+/// Number of `k`-element subsets of `n` bots, saturating past `usize::MAX`
+pub fn count_combinations(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    let k = k.min(n - k);
+    let mut count: u128 = 1;
+    for i in 0..k {
+        count = count * (n - i) as u128 / (i + 1) as u128;
+        if count > usize::MAX as u128 {
+            return usize::MAX;
+        }
+    }
+    count as usize
+}
+
+/// Seeds (1 for the best) in bracket order, so that pairing neighbours puts
+/// the best seeds furthest apart: 1 v 8, 4 v 5, 2 v 7, 3 v 6 for eight
+fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![1];
+    while order.len() < size {
+        let next = 2 * order.len() + 1;
+        order = order.iter().flat_map(|&seed| [seed, next - seed]).collect();
+    }
+    order
+}
+
+/// Stores a tournament and schedules its first round: every match of a round
+/// robin, or the first pairings of a Swiss tournament or bracket. Later rounds
+/// are scheduled by `run_rounds` as matches finish. Returns its id.
+pub async fn create(
+    pool: &SqlitePool,
+    manager: &SimulationManager,
+    plan: TournamentPlan,
+    bots: Vec<db::Bot>,
+) -> Result<String> {
+    let _guard = SCHEDULE_LOCK.lock().await;
+
+    let tournament_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO tournaments (id, name, format, table_size, rounds, settings)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&tournament_id)
+    .bind(&plan.name)
+    .bind(plan.format.as_str())
+    .bind(plan.table_size as i64)
+    .bind(plan.planned_rounds(bots.len()) as i64)
+    .bind(serde_json::to_string(&plan.settings)?)
+    .execute(pool)
+    .await?;
+
+    for (index, bot) in bots.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO tournament_entrants (tournament_id, bot_id, entry_index) VALUES (?, ?, ?)",
+        )
+        .bind(&tournament_id)
+        .bind(&bot.id)
+        .bind(index as i64)
+        .execute(pool)
+        .await?;
+    }
+
+    if plan.format == TournamentFormat::RoundRobin {
+        let tables = combinations(bots.len(), plan.table_size);
+        println!(
+            "[TOURNAMENT {}] Scheduling {} matches of {} bots",
+            tournament_id,
+            tables.len(),
+            plan.table_size
+        );
+        for (match_index, table) in tables.into_iter().enumerate() {
+            let seated = table.into_iter().map(|i| bots[i].clone()).collect();
+            schedule_match(
+                pool,
+                manager,
+                &tournament_id,
+                &plan.settings,
+                (match_index, 1, match_index),
+                seated,
+            )
+            .await?;
+        }
+    } else {
+        let tournament = load_tournament(pool, &tournament_id).await?;
+        advance_locked(pool, manager, &tournament).await?;
+    }
+
+    Ok(tournament_id)
+}
+
+/// Queues one match and records where it sits in the tournament
+async fn schedule_match(
+    pool: &SqlitePool,
+    manager: &SimulationManager,
+    tournament_id: &str,
+    settings: &SimulationSettings,
+    (match_index, round, slot): (usize, usize, usize),
+    bots: Vec<db::Bot>,
+) -> Result<()> {
+    let match_settings = SimulationSettings {
+        seed: settings.seed.wrapping_add(match_index as u64),
+        ..settings.clone()
+    };
+    let simulation_id = manager.create(bots, match_settings).await?;
+    sqlx::query(
+        "INSERT INTO tournament_matches (tournament_id, match_index, round, slot, simulation_id)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(tournament_id)
+    .bind(match_index as i64)
+    .bind(round as i64)
+    .bind(slot as i64)
+    .bind(&simulation_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Schedules the next round of every Swiss and knockout tournament as their
+/// matches finish, until the server stops. Rounds that became due while the
+/// server was down are scheduled first.
+pub async fn run_rounds(pool: SqlitePool, manager: Arc<SimulationManager>) {
+    let mut events = manager.subscribe();
+    advance_all(&pool, &manager).await;
+    loop {
+        match events.recv().await {
+            Ok(event) if event.is_final() => {
+                let Some(simulation_id) = event.simulation_id() else {
+                    continue;
+                };
+                if let Err(e) = match_finished(&pool, &manager, simulation_id).await {
+                    println!(
+                        "[TOURNAMENT] Could not schedule the round after simulation {}: {}",
+                        simulation_id, e
+                    );
+                }
+            }
+            Ok(_) => {}
+            // Missed events may have finished rounds, so check them all
+            Err(RecvError::Lagged(_)) => advance_all(&pool, &manager).await,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn match_finished(
+    pool: &SqlitePool,
+    manager: &SimulationManager,
+    simulation_id: &str,
+) -> Result<()> {
+    // Taken before the lookup, so a match that finished while its round was
+    // still being scheduled is found
+    let _guard = SCHEDULE_LOCK.lock().await;
+    let tournament_id: Option<String> =
+        sqlx::query_scalar("SELECT tournament_id FROM tournament_matches WHERE simulation_id = ?")
+            .bind(simulation_id)
+            .fetch_optional(pool)
+            .await?;
+    if let Some(tournament_id) = tournament_id {
+        let tournament = load_tournament(pool, &tournament_id).await?;
+        advance_locked(pool, manager, &tournament).await?;
+    }
+    Ok(())
+}
+
+async fn advance_all(pool: &SqlitePool, manager: &SimulationManager) {
+    let _guard = SCHEDULE_LOCK.lock().await;
+    let tournaments = match sqlx::query_as::<_, db::Tournament>(
+        "SELECT * FROM tournaments WHERE format != 'round_robin' ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await
+    {
+        Ok(tournaments) => tournaments,
+        Err(e) => {
+            println!("[TOURNAMENT] Could not load tournaments: {}", e);
+            return;
+        }
+    };
+    for tournament in &tournaments {
+        if let Err(e) = advance_locked(pool, manager, tournament).await {
+            println!(
+                "[TOURNAMENT {}] Could not schedule the next round: {}",
+                tournament.id, e
+            );
+        }
+    }
+}
+
+/// Schedules the next round once every match of the current one has
+/// completed. A match that failed or was cancelled halts the tournament.
+async fn advance_locked(
+    pool: &SqlitePool,
+    manager: &SimulationManager,
+    tournament: &db::Tournament,
+) -> Result<()> {
+    let format: TournamentFormat = tournament.format.parse()?;
+    if format == TournamentFormat::RoundRobin {
+        return Ok(());
+    }
+    let matches = load_matches(pool, &tournament.id).await?;
+    let byes = load_byes(pool, &tournament.id).await?;
+    let round = current_round(&matches, &byes);
+    if matches.iter().any(|game| game.status != "completed") || round >= tournament.rounds as usize
+    {
+        return Ok(());
+    }
+
+    let entrants = load_entrants(pool, &tournament.id).await?;
+    let settings: SimulationSettings = serde_json::from_str(&tournament.settings)?;
+    let next_round = round + 1;
+    let (pairings, new_byes) = match format {
+        TournamentFormat::Swiss => swiss_pairings(&entrants, &matches, &byes, next_round),
+        TournamentFormat::SingleElimination => {
+            bracket_pairings(&entrants, &matches, &byes, next_round)?
+        }
+        TournamentFormat::RoundRobin => unreachable!(),
+    };
+    println!(
+        "[TOURNAMENT {}] Scheduling round {} with {} matches",
+        tournament.id,
+        next_round,
+        pairings.len()
+    );
+
+    for (slot, bot_id) in &new_byes {
+        sqlx::query(
+            "INSERT INTO tournament_byes (tournament_id, round, slot, bot_id) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&tournament.id)
+        .bind(next_round as i64)
+        .bind(*slot as i64)
+        .bind(bot_id)
+        .execute(pool)
+        .await?;
+    }
+    let bots: HashMap<&str, &db::Bot> = entrants.iter().map(|bot| (bot.id.as_str(), bot)).collect();
+    for (match_index, (slot, pair)) in (matches.len()..).zip(pairings) {
+        let seated = pair
+            .iter()
+            .map(|bot_id| {
+                bots.get(bot_id.as_str())
+                    .map(|bot| (*bot).clone())
+                    .with_context(|| format!("bot {} is not entered in the tournament", bot_id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        schedule_match(
+            pool,
+            manager,
+            &tournament.id,
+            &settings,
+            (match_index, next_round, slot),
+            seated,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Matches and byes of a round, by slot: `(slot, bot ids)` and `(slot, bot id)`
+type Pairings = (Vec<(usize, Vec<String>)>, Vec<(usize, String)>);
+
+/// Pairs bots with similar records, top half against bottom half in the first
+/// round. With an odd number of bots the lowest-ranked bot that hasn't had a
+/// bye yet sits the round out, scoring as a win. Bots that have already met
+/// are kept apart where the pairings allow it.
+fn swiss_pairings(
+    entrants: &[db::Bot],
+    matches: &[MatchState],
+    byes: &[Bye],
+    round: usize,
+) -> Pairings {
+    let mut order: Vec<String> = if round == 1 {
+        entrants.iter().map(|bot| bot.id.clone()).collect()
+    } else {
+        compute_standings(entrants, matches, byes)
+            .into_iter()
+            .map(|standing| standing.bot_id)
+            .collect()
+    };
+
+    let mut new_byes = Vec::new();
+    let mut bye = None;
+    if order.len() % 2 == 1 {
+        let had_bye: HashSet<&str> = byes.iter().map(|bye| bye.bot_id.as_str()).collect();
+        let position = order
+            .iter()
+            .rposition(|bot_id| !had_bye.contains(bot_id.as_str()))
+            .unwrap_or(order.len() - 1);
+        bye = Some(order.remove(position));
+    }
+
+    let mut pairings = Vec::new();
+    if round == 1 {
+        let half = order.len() / 2;
+        for i in 0..half {
+            pairings.push((i, vec![order[i].clone(), order[i + half].clone()]));
+        }
+    } else {
+        let met: HashSet<(&str, &str)> = matches
+            .iter()
+            .flat_map(|game| {
+                game.seats.iter().flat_map(move |a| {
+                    game.seats
+                        .iter()
+                        .map(move |b| (a.bot_id.as_str(), b.bot_id.as_str()))
+                })
+            })
+            .collect();
+        for pair in pair_by_rank(&order, &met) {
+            pairings.push((pairings.len(), pair));
+        }
+    }
+
+    if let Some(bot_id) = bye {
+        new_byes.push((pairings.len(), bot_id));
+    }
+    (pairings, new_byes)
+}
+
+/// Most pairings `pair_by_rank` tries before it settles for a rematch
+const PAIRING_SEARCH_LIMIT: usize = 100_000;
+
+/// Pairs an even number of bots, best ranked first, each with the next best
+/// bot it hasn't met. When that leaves bots who have met each other at the
+/// end, earlier choices are revisited, so a rematch only happens when every
+/// way of pairing the bots needs one.
+fn pair_by_rank(order: &[String], met: &HashSet<(&str, &str)>) -> Vec<Vec<String>> {
+    /// Pairs `unpaired` without rematches, or `false` if that can't be done
+    /// within the search budget
+    fn search(
+        unpaired: &mut Vec<usize>,
+        order: &[String],
+        met: &HashSet<(&str, &str)>,
+        pairs: &mut Vec<(usize, usize)>,
+        budget: &mut usize,
+    ) -> bool {
+        if unpaired.is_empty() {
+            return true;
+        }
+        let first = unpaired.remove(0);
+        for i in 0..unpaired.len() {
+            if *budget == 0 {
+                break;
+            }
+            *budget -= 1;
+            let other = unpaired[i];
+            if met.contains(&(order[first].as_str(), order[other].as_str())) {
+                continue;
+            }
+            unpaired.remove(i);
+            pairs.push((first, other));
+            if search(unpaired, order, met, pairs, budget) {
+                return true;
+            }
+            pairs.pop();
+            unpaired.insert(i, other);
+        }
+        unpaired.insert(0, first);
+        false
+    }
+
+    let mut pairs = Vec::new();
+    let mut budget = PAIRING_SEARCH_LIMIT;
+    if !search(
+        &mut (0..order.len()).collect(),
+        order,
+        met,
+        &mut pairs,
+        &mut budget,
+    ) {
+        // Rematches can't be avoided: pair greedily, keeping them to the end
+        pairs.clear();
+        let mut unpaired: Vec<usize> = (0..order.len()).collect();
+        while unpaired.len() >= 2 {
+            let first = unpaired.remove(0);
+            let opponent = unpaired
+                .iter()
+                .position(|&other| !met.contains(&(order[first].as_str(), order[other].as_str())))
+                .unwrap_or(0);
+            pairs.push((first, unpaired.remove(opponent)));
+        }
+    }
+    pairs
+        .into_iter()
+        .map(|(a, b)| vec![order[a].clone(), order[b].clone()])
+        .collect()
+}
+
+/// The first round seeds the bracket by entry order, giving byes to the top
+/// seeds when the field isn't a power of two; each later slot is played by the
+/// winners of the two slots above it.
+fn bracket_pairings(
+    entrants: &[db::Bot],
+    matches: &[MatchState],
+    byes: &[Bye],
+    round: usize,
+) -> Result<Pairings> {
+    let mut pairings = Vec::new();
+    let mut new_byes = Vec::new();
+
+    if round == 1 {
+        let order = bracket_order(entrants.len().next_power_of_two());
+        for (slot, seeds) in order.chunks(2).enumerate() {
+            let top = entrants[seeds[0] - 1].id.clone();
+            match entrants.get(seeds[1] - 1) {
+                Some(bottom) => pairings.push((slot, vec![top, bottom.id.clone()])),
+                None => new_byes.push((slot, top)),
+            }
+        }
+        return Ok((pairings, new_byes));
+    }
+
+    let seeds = seed_positions(entrants);
+    let mut advancing: Vec<(usize, String)> = matches
+        .iter()
+        .filter(|game| game.round == round - 1)
+        .map(|game| {
+            game.winner(&seeds, TournamentFormat::SingleElimination)
+                .map(|winner| (game.slot, winner))
+                .with_context(|| format!("match {} has no winner", game.match_index))
+        })
+        .collect::<Result<_>>()?;
+    advancing.extend(
+        byes.iter()
+            .filter(|bye| bye.round == round - 1)
+            .map(|bye| (bye.slot, bye.bot_id.clone())),
+    );
+    advancing.sort();
+    for (slot, pair) in advancing.chunks(2).enumerate() {
+        match pair {
+            [(_, top), (_, bottom)] => pairings.push((slot, vec![top.clone(), bottom.clone()])),
+            [(_, top)] => new_byes.push((slot, top.clone())),
+            _ => unreachable!(),
+        }
+    }
+    Ok((pairings, new_byes))
+}
+
+fn current_round(matches: &[MatchState], byes: &[Bye]) -> usize {
+    matches
+        .iter()
+        .map(|game| game.round)
+        .chain(byes.iter().map(|bye| bye.round))
+        .max()
+        .unwrap_or(0)
+}
+
+fn seed_positions(entrants: &[db::Bot]) -> HashMap<String, usize> {
+    entrants
+        .iter()
+        .enumerate()
+        .map(|(i, bot)| (bot.id.clone(), i))
+        .collect()
+}
+
+async fn load_tournament(pool: &SqlitePool, tournament_id: &str) -> Result<db::Tournament> {
+    Ok(
+        sqlx::query_as::<_, db::Tournament>("SELECT * FROM tournaments WHERE id = ?")
+            .bind(tournament_id)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+/// Entrants in entry order, which is their seeding
+async fn load_entrants(pool: &SqlitePool, tournament_id: &str) -> Result<Vec<db::Bot>> {
+    Ok(sqlx::query_as::<_, db::Bot>(
+        "SELECT b.* FROM tournament_entrants te
+         JOIN bots b ON b.id = te.bot_id
+         WHERE te.tournament_id = ?
+         ORDER BY te.entry_index",
+    )
+    .bind(tournament_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+#[derive(sqlx::FromRow)]
+struct MatchSeatRow {
+    match_index: i64,
+    round: i64,
+    slot: i64,
+    simulation_id: String,
+    status: String,
+    games_completed: i64,
+    bot_id: String,
+    games_won: i64,
+    total_money: i64,
+}
+
+/// A tournament match with its simulation's status and totals so far
+struct MatchState {
+    match_index: usize,
+    round: usize,
+    slot: usize,
+    simulation_id: String,
+    status: String,
+    games_completed: i64,
+    /// In seat order
+    seats: Vec<Seat>,
+}
+
+struct Seat {
+    bot_id: String,
+    games_won: i64,
+    total_money: i64,
+}
+
+impl Seat {
+    /// Better result in a match: more money, then more games won
+    fn outcome(&self, other: &Seat) -> Ordering {
+        (self.total_money, self.games_won).cmp(&(other.total_money, other.games_won))
+    }
+}
+
+impl MatchState {
+    /// The bot that won a completed match. Level results are a draw, except
+    /// in a knockout where the better seed goes through.
+    fn winner(&self, seeds: &HashMap<String, usize>, format: TournamentFormat) -> Option<String> {
+        if self.status != "completed" {
+            return None;
+        }
+        let best = self.seats.iter().max_by(|a, b| a.outcome(b))?;
+        let level: Vec<&Seat> = self
+            .seats
+            .iter()
+            .filter(|seat| seat.outcome(best).is_eq())
+            .collect();
+        match level.as_slice() {
+            [only] => Some(only.bot_id.clone()),
+            _ if format == TournamentFormat::SingleElimination => level
+                .iter()
+                .min_by_key(|seat| seeds.get(&seat.bot_id).copied().unwrap_or(usize::MAX))
+                .map(|seat| seat.bot_id.clone()),
+            _ => None,
+        }
+    }
+}
+
+async fn load_matches(pool: &SqlitePool, tournament_id: &str) -> Result<Vec<MatchState>> {
+    let rows = sqlx::query_as::<_, MatchSeatRow>(
+        "SELECT tm.match_index, tm.round, tm.slot, tm.simulation_id, s.status, s.games_completed,
+                sp.bot_id, sp.games_won, sp.total_money
+         FROM tournament_matches tm
+         JOIN simulations s ON s.id = tm.simulation_id
+         JOIN simulation_participants sp ON sp.simulation_id = tm.simulation_id
+         WHERE tm.tournament_id = ?
+         ORDER BY tm.match_index, sp.player_index",
+    )
+    .bind(tournament_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut matches: Vec<MatchState> = Vec::new();
+    for row in rows {
+        let seat = Seat {
+            bot_id: row.bot_id,
+            games_won: row.games_won,
+            total_money: row.total_money,
+        };
+        match matches.last_mut() {
+            Some(game) if game.match_index == row.match_index as usize => game.seats.push(seat),
+            _ => matches.push(MatchState {
+                match_index: row.match_index as usize,
+                round: row.round as usize,
+                slot: row.slot as usize,
+                simulation_id: row.simulation_id,
+                status: row.status,
+                games_completed: row.games_completed,
+                seats: vec![seat],
+            }),
+        }
+    }
+    Ok(matches)
+}
+
+#[derive(sqlx::FromRow)]
+struct Bye {
+    #[sqlx(try_from = "i64")]
+    round: usize,
+    #[sqlx(try_from = "i64")]
+    slot: usize,
+    bot_id: String,
+}
+
+async fn load_byes(pool: &SqlitePool, tournament_id: &str) -> Result<Vec<Bye>> {
+    Ok(sqlx::query_as::<_, Bye>(
+        "SELECT round, slot, bot_id FROM tournament_byes
+         WHERE tournament_id = ?
+         ORDER BY round, slot",
+    )
+    .bind(tournament_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// How far a tournament has got
+#[derive(Debug, Clone, Serialize)]
+pub struct TournamentProgress {
+    /// `running` until every round has been played and every match has
+    /// completed, failed or been cancelled. Swiss and knockout tournaments
+    /// are `failed` once a match fails or is cancelled, as the next round
+    /// can't be drawn without its result.
+    pub status: String,
+    /// Latest round scheduled
+    pub current_round: usize,
+    pub matches_total: usize,
+    pub matches_completed: usize,
+    pub matches_failed: usize,
+}
+
+pub async fn progress(
+    pool: &SqlitePool,
+    tournament: &db::Tournament,
+) -> Result<TournamentProgress> {
+    let format: TournamentFormat = tournament.format.parse()?;
+    let matches = load_matches(pool, &tournament.id).await?;
+    let byes = load_byes(pool, &tournament.id).await?;
+
+    let count = |wanted: &[&str]| {
+        matches
+            .iter()
+            .filter(|game| wanted.contains(&game.status.as_str()))
+            .count()
+    };
+    let matches_completed = count(&["completed"]);
+    let matches_failed = count(&["failed", "cancelled"]);
+    let current_round = current_round(&matches, &byes);
+    let all_finished = matches_completed + matches_failed == matches.len();
+    let status = if format != TournamentFormat::RoundRobin && matches_failed > 0 {
+        "failed"
+    } else if all_finished && current_round >= tournament.rounds as usize {
+        "completed"
+    } else {
+        "running"
+    };
+
+    Ok(TournamentProgress {
+        status: status.to_string(),
+        current_round,
+        matches_total: matches.len(),
+        matches_completed,
+        matches_failed,
+    })
+}
+
+/// A bot's record over a tournament's completed matches
+#[derive(Debug, Clone, Serialize)]
+pub struct TournamentStanding {
+    /// 1 for the leader; bots tied on points and every tie-break share a rank
+    pub rank: usize,
+    pub bot_id: String,
+    pub bot_name: String,
+    /// One point for every opponent a bot beat in a match, half a point for
+    /// every one it drew with, and one for every bye
+    pub points: f64,
+    pub matches_played: u32,
+    pub byes: u32,
+    pub games_played: i64,
+    pub games_won: i64,
+    pub total_money: i64,
+    pub average_money_per_game: f64,
+}
+
+impl TournamentStanding {
+    /// Orders standings best first: by points, then by total money, then by
+    /// games won
+    pub fn compare(&self, other: &Self) -> Ordering {
+        other
+            .points
+            .total_cmp(&self.points)
+            .then(other.total_money.cmp(&self.total_money))
+            .then(other.games_won.cmp(&self.games_won))
+    }
+}
+
+/// Every entrant's record over the completed matches, best first
+pub async fn standings(pool: &SqlitePool, tournament_id: &str) -> Result<Vec<TournamentStanding>> {
+    let entrants = load_entrants(pool, tournament_id).await?;
+    let matches = load_matches(pool, tournament_id).await?;
+    let byes = load_byes(pool, tournament_id).await?;
+    Ok(compute_standings(&entrants, &matches, &byes))
+}
+
+/// Standings sorted best first; bots level on everything stay in entry order
+fn compute_standings(
+    entrants: &[db::Bot],
+    matches: &[MatchState],
+    byes: &[Bye],
+) -> Vec<TournamentStanding> {
+    let mut standings: Vec<TournamentStanding> = entrants
+        .iter()
+        .map(|bot| TournamentStanding {
+            rank: 0,
+            bot_id: bot.id.clone(),
+            bot_name: bot.name.clone(),
+            points: 0.0,
+            matches_played: 0,
+            byes: 0,
+            games_played: 0,
+            games_won: 0,
+            total_money: 0,
+            average_money_per_game: 0.0,
+        })
+        .collect();
+    let positions = seed_positions(entrants);
+
+    for game in matches.iter().filter(|game| game.status == "completed") {
+        for seat in &game.seats {
+            let Some(&position) = positions.get(&seat.bot_id) else {
+                continue;
+            };
+            let standing = &mut standings[position];
+            standing.matches_played += 1;
+            standing.games_played += game.games_completed;
+            standing.games_won += seat.games_won;
+            standing.total_money += seat.total_money;
+            for other in &game.seats {
+                if other.bot_id != seat.bot_id {
+                    standing.points += match seat.outcome(other) {
+                        Ordering::Greater => 1.0,
+                        Ordering::Equal => 0.5,
+                        Ordering::Less => 0.0,
+                    };
+                }
+            }
+        }
+    }
+    for bye in byes {
+        if let Some(&position) = positions.get(&bye.bot_id) {
+            standings[position].byes += 1;
+            standings[position].points += 1.0;
+        }
+    }
+
+    for standing in &mut standings {
+        standing.average_money_per_game =
+            standing.total_money as f64 / standing.games_played.max(1) as f64;
+    }
+    standings.sort_by(|a, b| a.compare(b));
+    for i in 0..standings.len() {
+        standings[i].rank = if i > 0 && standings[i].compare(&standings[i - 1]).is_eq() {
+            standings[i - 1].rank
+        } else {
+            i + 1
+        };
+    }
+    standings
+}
+
+/// The matches and byes of one round, in slot order, for drawing pairings
+/// and brackets
+#[derive(Debug, Clone, Serialize)]
+pub struct TournamentRound {
+    pub round: usize,
+    pub matches: Vec<RoundMatch>,
+    pub byes: Vec<RoundBye>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundMatch {
+    /// Position in the round; in a bracket, slots `2i` and `2i + 1` feed slot
+    /// `i` of the next round
+    pub slot: usize,
+    pub match_index: usize,
+    pub simulation_id: String,
+    pub status: String,
+    /// In seat order, with their totals so far
+    pub bots: Vec<MatchBot>,
+    /// Set once the match has completed, unless it was drawn
+    pub winner_bot_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchBot {
+    pub bot_id: String,
+    pub bot_name: String,
+    pub games_won: i64,
+    pub total_money: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundBye {
+    pub slot: usize,
+    pub bot_id: String,
+    pub bot_name: String,
+}
+
+/// Every round scheduled so far
+pub async fn rounds(
+    pool: &SqlitePool,
+    tournament: &db::Tournament,
+) -> Result<Vec<TournamentRound>> {
+    let format: TournamentFormat = tournament.format.parse()?;
+    let entrants = load_entrants(pool, &tournament.id).await?;
+    let matches = load_matches(pool, &tournament.id).await?;
+    let byes = load_byes(pool, &tournament.id).await?;
+    let seeds = seed_positions(&entrants);
+    let name = |bot_id: &str| {
+        entrants
+            .iter()
+            .find(|bot| bot.id == bot_id)
+            .map(|bot| bot.name.clone())
+            .unwrap_or_default()
+    };
+
+    let mut rounds: Vec<TournamentRound> = (1..=current_round(&matches, &byes))
+        .map(|round| TournamentRound {
+            round,
+            matches: Vec::new(),
+            byes: Vec::new(),
+        })
+        .collect();
+    for game in &matches {
+        rounds[game.round - 1].matches.push(RoundMatch {
+            slot: game.slot,
+            match_index: game.match_index,
+            simulation_id: game.simulation_id.clone(),
+            status: game.status.clone(),
+            bots: game
+                .seats
+                .iter()
+                .map(|seat| MatchBot {
+                    bot_id: seat.bot_id.clone(),
+                    bot_name: name(&seat.bot_id),
+                    games_won: seat.games_won,
+                    total_money: seat.total_money,
+                })
+                .collect(),
+            winner_bot_id: game.winner(&seeds, format),
+        });
+    }
+    for bye in &byes {
+        rounds[bye.round - 1].byes.push(RoundBye {
+            slot: bye.slot,
+            bot_id: bye.bot_id.clone(),
+            bot_name: name(&bye.bot_id),
+        });
+    }
+    for round in &mut rounds {
+        round.matches.sort_by_key(|game| game.slot);
+    }
+    Ok(rounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Entrants `b1` to `b<n>`, in seed order
+    fn bots(n: usize) -> Vec<db::Bot> {
+        (1..=n)
+            .map(|i| db::Bot {
+                id: format!("b{i}"),
+                name: format!("bot {i}"),
+                description: None,
+                wasm_hash: String::new(),
+                file_path: String::new(),
+                created_at: String::new(),
+                world_version: None,
+            })
+            .collect()
+    }
+
+    /// A completed match; each seat is `(bot id, games won, total money)`
+    fn completed(
+        match_index: usize,
+        round: usize,
+        slot: usize,
+        seats: &[(&str, i64, i64)],
+    ) -> MatchState {
+        MatchState {
+            match_index,
+            round,
+            slot,
+            simulation_id: format!("sim-{match_index}"),
+            status: "completed".to_string(),
+            games_completed: 100,
+            seats: seats
+                .iter()
+                .map(|&(bot_id, games_won, total_money)| Seat {
+                    bot_id: bot_id.to_string(),
+                    games_won,
+                    total_money,
+                })
+                .collect(),
+        }
+    }
+
+    fn bye(round: usize, slot: usize, bot_id: &str) -> Bye {
+        Bye {
+            round,
+            slot,
+            bot_id: bot_id.to_string(),
+        }
+    }
+
+    fn pairs(pairs: &[(usize, &[&str])]) -> Vec<(usize, Vec<String>)> {
+        pairs
+            .iter()
+            .map(|(slot, bots)| (*slot, bots.iter().map(|bot| bot.to_string()).collect()))
+            .collect()
+    }
+
+    fn byes(byes: &[(usize, &str)]) -> Vec<(usize, String)> {
+        byes.iter()
+            .map(|(slot, bot)| (*slot, bot.to_string()))
+            .collect()
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn bracket_order_keeps_top_seeds_apart() {
+        assert_eq!(bracket_order(2), [1, 2]);
+        assert_eq!(bracket_order(4), [1, 4, 2, 3]);
+        assert_eq!(bracket_order(8), [1, 8, 4, 5, 2, 7, 3, 6]);
+    }
+
+    #[test]
+    fn first_bracket_round_gives_top_seeds_byes() {
+        let first_round = |n| bracket_pairings(&bots(n), &[], &[], 1).unwrap();
+
+        assert_eq!(
+            first_round(3),
+            (pairs(&[(1, &["b2", "b3"])]), byes(&[(0, "b1")]))
+        );
+        assert_eq!(
+            first_round(5),
+            (
+                pairs(&[(1, &["b4", "b5"])]),
+                byes(&[(0, "b1"), (2, "b2"), (3, "b3")])
+            )
+        );
+        assert_eq!(
+            first_round(6),
+            (
+                pairs(&[(1, &["b4", "b5"]), (3, &["b3", "b6"])]),
+                byes(&[(0, "b1"), (2, "b2")])
+            )
+        );
+        assert_eq!(
+            first_round(8),
+            (
+                pairs(&[
+                    (0, &["b1", "b8"]),
+                    (1, &["b4", "b5"]),
+                    (2, &["b2", "b7"]),
+                    (3, &["b3", "b6"]),
+                ]),
+                byes(&[])
+            )
+        );
+    }
+
+    #[test]
+    fn later_bracket_rounds_meet_bye_holders() {
+        let entrants = bots(5);
+        let matches = [completed(0, 1, 1, &[("b4", 40, -10), ("b5", 60, 10)])];
+        let round_one_byes = [bye(1, 0, "b1"), bye(1, 2, "b2"), bye(1, 3, "b3")];
+        assert_eq!(
+            bracket_pairings(&entrants, &matches, &round_one_byes, 2).unwrap(),
+            (pairs(&[(0, &["b1", "b5"]), (1, &["b2", "b3"])]), byes(&[]))
+        );
+
+        let entrants = bots(3);
+        let matches = [completed(0, 1, 1, &[("b2", 50, -5), ("b3", 50, 5)])];
+        assert_eq!(
+            bracket_pairings(&entrants, &matches, &[bye(1, 0, "b1")], 2).unwrap(),
+            (pairs(&[(0, &["b1", "b3"])]), byes(&[]))
+        );
+    }
+
+    #[test]
+    fn level_knockout_match_goes_to_better_seed() {
+        let entrants = bots(2);
+        let seeds = seed_positions(&entrants);
+        let level = completed(0, 1, 0, &[("b2", 50, 0), ("b1", 50, 0)]);
+        assert_eq!(
+            level.winner(&seeds, TournamentFormat::SingleElimination),
+            Some("b1".to_string())
+        );
+        assert_eq!(level.winner(&seeds, TournamentFormat::Swiss), None);
+    }
+
+    #[test]
+    fn pairing_avoids_rematches_while_it_can() {
+        let order = ids(&["a", "b", "c", "d", "e", "f"]);
+        // Pairing neighbours would leave e and f to meet again
+        let met = HashSet::from([("e", "f"), ("f", "e")]);
+        assert_eq!(
+            pair_by_rank(&order, &met),
+            [ids(&["a", "b"]), ids(&["c", "e"]), ids(&["d", "f"])]
+        );
+
+        let met = HashSet::from([("a", "b"), ("b", "a"), ("c", "d"), ("d", "c")]);
+        assert_eq!(
+            pair_by_rank(&ids(&["a", "b", "c", "d"]), &met),
+            [ids(&["a", "c"]), ids(&["b", "d"])]
+        );
+    }
+
+    #[test]
+    fn pairing_rematches_only_when_unavoidable() {
+        let met = HashSet::from([("a", "b"), ("b", "a")]);
+        assert_eq!(pair_by_rank(&ids(&["a", "b"]), &met), [ids(&["a", "b"])]);
+    }
+
+    #[test]
+    fn swiss_rounds_never_repeat_pairings() {
+        let entrants = bots(4);
+        let (first, no_byes) = swiss_pairings(&entrants, &[], &[], 1);
+        assert_eq!(first, pairs(&[(0, &["b1", "b3"]), (1, &["b2", "b4"])]));
+        assert!(no_byes.is_empty());
+
+        // The winners meet, and so do the losers
+        let mut matches = vec![
+            completed(0, 1, 0, &[("b1", 60, 20), ("b3", 40, -20)]),
+            completed(1, 1, 1, &[("b2", 55, 10), ("b4", 45, -10)]),
+        ];
+        let (second, _) = swiss_pairings(&entrants, &matches, &[], 2);
+        assert_eq!(second, pairs(&[(0, &["b1", "b2"]), (1, &["b4", "b3"])]));
+
+        // b1 has met both bots just below it, so it drops down to b4
+        matches.push(completed(2, 2, 0, &[("b1", 60, 20), ("b2", 40, -20)]));
+        matches.push(completed(3, 2, 1, &[("b4", 45, -10), ("b3", 55, 10)]));
+        let (third, _) = swiss_pairings(&entrants, &matches, &[], 3);
+        assert_eq!(third, pairs(&[(0, &["b1", "b4"]), (1, &["b2", "b3"])]));
+    }
+
+    #[test]
+    fn swiss_bye_goes_to_lowest_ranked_bot_without_one() {
+        let entrants = bots(3);
+        let (_, first_byes) = swiss_pairings(&entrants, &[], &[], 1);
+        assert_eq!(first_byes, byes(&[(1, "b3")]));
+
+        let matches = [completed(0, 1, 0, &[("b1", 60, 20), ("b2", 40, -20)])];
+        let (second, second_byes) = swiss_pairings(&entrants, &matches, &[bye(1, 1, "b3")], 2);
+        // b3 leads with its bye, b2 is last and hasn't had one
+        assert_eq!(second, pairs(&[(0, &["b1", "b3"])]));
+        assert_eq!(second_byes, byes(&[(1, "b2")]));
+    }
+
+    #[test]
+    fn standings_break_ties_on_money_then_games_won() {
+        let entrants = bots(4);
+        // b1 and b3 both win a match and make the same money, b3 winning more
+        // games; b2 and b4 both lose by the same money
+        let matches = [
+            completed(0, 1, 0, &[("b1", 55, 50), ("b2", 45, -50)]),
+            completed(1, 1, 1, &[("b3", 60, 50), ("b4", 40, -50)]),
+        ];
+        let order: Vec<(String, usize)> = compute_standings(&entrants, &matches, &[])
+            .into_iter()
+            .map(|standing| (standing.bot_id, standing.rank))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                ("b3".to_string(), 1),
+                ("b1".to_string(), 2),
+                ("b2".to_string(), 3),
+                ("b4".to_string(), 4),
+            ]
+        );
+
+        // More money outranks more games won
+        let matches = [
+            completed(0, 1, 0, &[("b1", 40, 60), ("b2", 60, -60)]),
+            completed(1, 1, 1, &[("b3", 60, 50), ("b4", 40, -50)]),
+        ];
+        let leaders: Vec<String> = compute_standings(&entrants, &matches, &[])
+            .into_iter()
+            .map(|standing| standing.bot_id)
+            .take(2)
+            .collect();
+        assert_eq!(leaders, ids(&["b1", "b3"]));
+    }
+
+    #[test]
+    fn standings_level_on_everything_share_a_rank() {
+        let entrants = bots(2);
+        let matches = [completed(0, 1, 0, &[("b1", 50, 0), ("b2", 50, 0)])];
+        let standings = compute_standings(&entrants, &matches, &[]);
+        assert_eq!(standings[0].points, 0.5);
+        assert_eq!(standings.iter().map(|s| s.rank).collect::<Vec<_>>(), [1, 1]);
+        assert_eq!(standings[0].bot_id, "b1");
+    }
+}
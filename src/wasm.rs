@@ -0,0 +1,745 @@
+use anyhow::{anyhow, Context, Result};
+use cap_rand::SeedableRng as _;
+use std::fmt;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+use wasmtime::component::*;
+use wasmtime::{Config, Engine, ResourceLimiter, Store, Trap};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+
+use crate::cache::ComponentCache;
+use crate::game::{derive_seed, GameState, TurnHistoryEntry, TurnOutcome};
+use crate::recording::BustReason;
+use crate::strategy::{DisqualificationReason, Strategy};
+
+/// Default CPU budget for a single call into a bot (a `should-roll` decision
+/// or a lifecycle callback), in wasmtime fuel units (roughly one per executed
+/// WASM instruction)
+pub const DEFAULT_FUEL_PER_DECISION: u64 = 100_000_000;
+
+/// Wall-clock safety net for a single call into a bot. Fuel is the budget
+/// that disqualifies bots, since it doesn't depend on how busy the host is;
+/// this only stops a call stuck outside of WASM instructions. Whether it is
+/// hit depends on wall-clock time, so rather than disqualifying the bot (and
+/// making seeded results differ between runs) it fails the simulation with
+/// `WallClockLimitExceeded`.
+pub const DECISION_WALL_CLOCK_LIMIT: Duration = Duration::from_secs(30);
+
+/// How often the engine's epoch is advanced; the granularity of
+/// `DECISION_WALL_CLOCK_LIMIT`
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Epoch deadline while a component is being instantiated, in ticks
+const INSTANTIATION_DEADLINE_TICKS: u64 = 1000;
+
+/// A call into a bot ran past `DECISION_WALL_CLOCK_LIMIT`
+#[derive(Debug)]
+pub struct WallClockLimitExceeded;
+
+impl fmt::Display for WallClockLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a call into a bot ran past the {}s wall-clock safety limit",
+            DECISION_WALL_CLOCK_LIMIT.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for WallClockLimitExceeded {}
+
+/// A failure on the host's side of a call into a bot. Host code that can fail
+/// around or during a call attaches this to its errors, so that the failure
+/// fails the simulation rather than disqualifying the bot.
+#[derive(Debug)]
+pub struct HostError(pub &'static str);
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for HostError {}
+
+/// Bindings for each supported version of the `pig-pen:player` world. The
+/// latest version lives in `wit/`, older ones are frozen under `wit/legacy/`.
+mod bindings {
+    pub mod v0_1 {
+        wasmtime::component::bindgen!({
+            path: "wit/legacy/player-0.1.0",
+            world: "player",
+        });
+    }
+
+    pub mod v0_2 {
+        wasmtime::component::bindgen!({
+            path: "wit/legacy/player-0.2.0",
+            world: "player",
+        });
+    }
+
+    // Generated for the superset world; the `lifecycle` export is looked up
+    // separately so that plain `player` components load too
+    pub mod v0_3 {
+        wasmtime::component::bindgen!({
+            path: "wit",
+            world: "learning-player",
+        });
+    }
+}
+
+use bindings::{v0_1, v0_2, v0_3};
+use v0_3::exports::pig_pen::player::{lifecycle, strategy as strategy_v0_3};
+use v0_3::pig_pen::player::types::{
+    Bust as WitBust, BustReason as WitBustReason, TurnOutcome as WitTurnOutcome,
+};
+
+/// A version of the `pig-pen:player` world the host can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldVersion {
+    V0_1,
+    V0_2,
+    V0_3,
+}
+
+impl WorldVersion {
+    /// Supported versions, newest first
+    pub const ALL: &'static [WorldVersion] =
+        &[WorldVersion::V0_3, WorldVersion::V0_2, WorldVersion::V0_1];
+
+    /// The version described by `wit/strategy.wit`
+    pub const LATEST: WorldVersion = WorldVersion::V0_3;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorldVersion::V0_1 => "0.1.0",
+            WorldVersion::V0_2 => "0.2.0",
+            WorldVersion::V0_3 => "0.3.0",
+        }
+    }
+
+    /// Name of the `strategy` export a component targeting this version provides
+    fn strategy_export(&self) -> &'static str {
+        match self {
+            WorldVersion::V0_1 => "pig-pen:player/strategy@0.1.0",
+            WorldVersion::V0_2 => "pig-pen:player/strategy@0.2.0",
+            WorldVersion::V0_3 => "pig-pen:player/strategy@0.3.0",
+        }
+    }
+
+    /// Works out which world version a compiled component targets from the
+    /// `strategy` interface it exports
+    pub fn detect(component: &Component) -> Result<WorldVersion> {
+        WorldVersion::ALL
+            .iter()
+            .copied()
+            .find(|version| {
+                component
+                    .get_export_index(None, version.strategy_export())
+                    .is_some()
+            })
+            .with_context(|| {
+                format!(
+                    "Component doesn't export a pig-pen:player strategy interface (supported versions: {})",
+                    WorldVersion::ALL
+                        .iter()
+                        .map(|version| version.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for WorldVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An instantiated strategy component, by the world version it targets
+enum Player {
+    /// Receives the whole turn history with every decision
+    V0_1(v0_1::Player),
+    /// Reads the turn history through the host's `history` import
+    V0_2(v0_2::Player),
+    /// As 0.2.0, optionally exporting `lifecycle` to hear how turns and games end
+    V0_3 {
+        strategy: strategy_v0_3::Guest,
+        lifecycle: Option<lifecycle::Guest>,
+    },
+}
+
+impl Player {
+    fn lifecycle(&self) -> Option<&lifecycle::Guest> {
+        match self {
+            Player::V0_3 { lifecycle, .. } => lifecycle.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// A component's exports, looked up once against its `InstancePre` so that
+/// creating further instances stays cheap
+enum PlayerIndices {
+    V0_1(v0_1::PlayerIndices),
+    V0_2(v0_2::PlayerIndices),
+    V0_3 {
+        strategy: strategy_v0_3::GuestIndices,
+        lifecycle: Option<lifecycle::GuestIndices>,
+    },
+}
+
+impl PlayerIndices {
+    fn new(version: WorldVersion, instance_pre: &InstancePre<StoreData>) -> Result<Self> {
+        Ok(match version {
+            WorldVersion::V0_1 => PlayerIndices::V0_1(v0_1::PlayerIndices::new(instance_pre)?),
+            WorldVersion::V0_2 => PlayerIndices::V0_2(v0_2::PlayerIndices::new(instance_pre)?),
+            WorldVersion::V0_3 => PlayerIndices::V0_3 {
+                strategy: strategy_v0_3::GuestIndices::new(instance_pre)?,
+                // Optional export: components built for plain `player` don't have it
+                lifecycle: lifecycle::GuestIndices::new(instance_pre).ok(),
+            },
+        })
+    }
+
+    /// Creates a new instance of the component in `store`
+    fn instantiate(
+        &self,
+        instance_pre: &InstancePre<StoreData>,
+        store: &mut Store<StoreData>,
+    ) -> Result<Player> {
+        let instance = instance_pre
+            .instantiate(&mut *store)
+            .context("Failed to instantiate WASM component")?;
+        Ok(match self {
+            PlayerIndices::V0_1(indices) => Player::V0_1(indices.load(&mut *store, &instance)?),
+            PlayerIndices::V0_2(indices) => Player::V0_2(indices.load(&mut *store, &instance)?),
+            PlayerIndices::V0_3 {
+                strategy,
+                lifecycle,
+            } => Player::V0_3 {
+                strategy: strategy.load(&mut *store, &instance)?,
+                lifecycle: lifecycle
+                    .as_ref()
+                    .map(|indices| indices.load(&mut *store, &instance))
+                    .transpose()?,
+            },
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct StoreData {
+    pub current_memory_bytes: u64,
+    pub peak_memory_bytes: u64,
+    pub memory_limit: Option<u64>,
+    pub memory_limit_hit: bool,
+    pub fuel_per_decision: Option<u64>,
+    pub fuel_remaining: Option<u64>, // Per-simulation budget left, if any
+    pub fuel_consumed: u64,
+    pub disqualification: Option<DisqualificationReason>,
+    pub wasi_ctx: WasiCtx,
+    pub resource_table: ResourceTable,
+    /// Host copy of the current game's rolls, served through the `history` import
+    pub history: Vec<TurnHistoryEntry>,
+}
+
+impl StoreData {
+    /// Fresh store state with default limits and WASI random sources seeded
+    /// from `seed`
+    fn new(seed: u64) -> Self {
+        let wasi_ctx = WasiCtxBuilder::new()
+            .secure_random(cap_rand::rngs::StdRng::seed_from_u64(seed))
+            .insecure_random(cap_rand::rngs::StdRng::seed_from_u64(!seed))
+            .insecure_random_seed(seed as u128)
+            .build();
+
+        StoreData {
+            current_memory_bytes: 0,
+            peak_memory_bytes: 0,
+            memory_limit: Some(100 * 1024 * 1024), // 100MB limit per strategy
+            memory_limit_hit: false,
+            fuel_per_decision: Some(DEFAULT_FUEL_PER_DECISION),
+            fuel_remaining: None,
+            fuel_consumed: 0,
+            disqualification: None,
+            wasi_ctx,
+            resource_table: ResourceTable::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Works out why a call into the component failed. `None` if the failure
+    /// isn't the bot's doing: a `HostError`, or the wall-clock safety net
+    /// interrupting it. Anything else the call returns is blamed on the bot.
+    fn disqualification_for(&self, error: &anyhow::Error) -> Option<DisqualificationReason> {
+        let trap = error.downcast_ref::<Trap>();
+        if error.downcast_ref::<HostError>().is_some() || trap == Some(&Trap::Interrupt) {
+            // Depends on wall-clock time, see `DECISION_WALL_CLOCK_LIMIT`
+            return None;
+        }
+        // A refused memory.grow usually surfaces later as some other trap
+        if self.memory_limit_hit {
+            return Some(DisqualificationReason::MemoryLimit);
+        }
+        Some(match trap {
+            Some(Trap::OutOfFuel) => DisqualificationReason::FuelExhausted,
+            Some(_) => DisqualificationReason::Trap,
+            // Values that couldn't be lifted, the component exiting and the like
+            None => DisqualificationReason::InvalidOutput,
+        })
+    }
+
+    /// Brings the history copy up to date by appending the rolls made since
+    /// the last call into the bot, so each roll is copied once per game.
+    fn sync_history(&mut self, turn_history: &[TurnHistoryEntry]) {
+        if self.history.len() > turn_history.len() {
+            // A new game started without `on_game_start`
+            self.history.clear();
+        }
+        self.history
+            .extend_from_slice(&turn_history[self.history.len()..]);
+    }
+}
+
+impl v0_2::pig_pen::player::types::Host for StoreData {}
+impl v0_3::pig_pen::player::types::Host for StoreData {}
+
+impl v0_2::pig_pen::player::history::Host for StoreData {
+    fn len(&mut self) -> u32 {
+        self.history.len() as u32
+    }
+
+    fn range(&mut self, start: u32, count: u32) -> Vec<TurnHistoryEntry> {
+        let start = (start as usize).min(self.history.len());
+        let end = start.saturating_add(count as usize).min(self.history.len());
+        self.history[start..end].to_vec()
+    }
+}
+
+impl v0_3::pig_pen::player::history::Host for StoreData {
+    fn len(&mut self) -> u32 {
+        self.history.len() as u32
+    }
+
+    fn range(&mut self, start: u32, count: u32) -> Vec<TurnHistoryEntry> {
+        let start = (start as usize).min(self.history.len());
+        let end = start.saturating_add(count as usize).min(self.history.len());
+        self.history[start..end].to_vec()
+    }
+}
+
+impl WasiView for StoreData {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi_ctx,
+            table: &mut self.resource_table,
+        }
+    }
+}
+
+impl ResourceLimiter for StoreData {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        let desired_bytes = desired as u64;
+
+        self.current_memory_bytes = desired_bytes;
+        if desired_bytes > self.peak_memory_bytes {
+            self.peak_memory_bytes = desired_bytes;
+        }
+
+        // Check against our limit if set
+        if let Some(limit) = self.memory_limit {
+            if desired_bytes > limit {
+                self.memory_limit_hit = true;
+                return Ok(false);
+            }
+        }
+
+        // Check against maximum if provided
+        if let Some(max) = maximum {
+            if desired > max {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+pub struct WasmStrategy {
+    store: Store<StoreData>,
+    player: Player,
+    instance_pre: InstancePre<StoreData>,
+    indices: PlayerIndices,
+    seed: u64,
+}
+
+impl WasmStrategy {
+    pub fn new(engine: &Engine, wasm_bytes: &[u8]) -> Result<Self> {
+        Self::with_seed(engine, wasm_bytes, rand::random())
+    }
+
+    /// Creates a strategy whose WASI random sources are seeded from `seed`, so
+    /// that bots drawing their own randomness behave reproducibly too.
+    pub fn with_seed(engine: &Engine, wasm_bytes: &[u8], seed: u64) -> Result<Self> {
+        let component = Component::from_binary(engine, wasm_bytes)
+            .context("Failed to compile WASM component")?;
+        Self::from_component(engine, &component, seed)
+    }
+
+    /// Instantiates an already compiled component, e.g. one from the `ComponentCache`
+    pub fn from_component(engine: &Engine, component: &Component, seed: u64) -> Result<Self> {
+        Self::start(engine, component, seed)?
+            .map_err(|reason| anyhow!("Bot was disqualified while starting: {reason}"))
+    }
+
+    /// Like `from_component`, but a bot that traps or breaks a limit while
+    /// starting up gives the reason it is disqualified instead of an error,
+    /// just as when it fails to start over in `reset`
+    pub fn start(
+        engine: &Engine,
+        component: &Component,
+        seed: u64,
+    ) -> Result<Result<Self, DisqualificationReason>> {
+        let version = WorldVersion::detect(component)?;
+
+        let mut linker = Linker::new(engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+        v0_2::Player::add_to_linker::<_, HasSelf<StoreData>>(&mut linker, |data| data)?;
+        v0_3::LearningPlayer::add_to_linker::<_, HasSelf<StoreData>>(&mut linker, |data| data)?;
+
+        let instance_pre = linker
+            .instantiate_pre(component)
+            .context("Failed to instantiate WASM component")?;
+        let indices = PlayerIndices::new(version, &instance_pre)?;
+
+        let mut store = new_store(engine, StoreData::new(seed))?;
+        match indices.instantiate(&instance_pre, &mut store) {
+            Ok(player) => Ok(Ok(WasmStrategy {
+                store,
+                player,
+                instance_pre,
+                indices,
+                seed,
+            })),
+            Err(e) => {
+                let Some(reason) = store.data().disqualification_for(&e) else {
+                    return Err(e.context("Failed to start the bot"));
+                };
+                tracing::warn!("Strategy disqualified ({reason}) while starting: {e:#}");
+                Ok(Err(reason))
+            }
+        }
+    }
+
+    /// The world version the component targets
+    pub fn world_version(&self) -> WorldVersion {
+        match self.player {
+            Player::V0_1(_) => WorldVersion::V0_1,
+            Player::V0_2(_) => WorldVersion::V0_2,
+            Player::V0_3 { .. } => WorldVersion::V0_3,
+        }
+    }
+
+    /// Fuel available to the next call: the per-decision budget, capped by
+    /// whatever is left of the per-simulation budget
+    fn decision_fuel_budget(&self) -> u64 {
+        let data = self.store.data();
+        data.fuel_per_decision
+            .unwrap_or(u64::MAX)
+            .min(data.fuel_remaining.unwrap_or(u64::MAX))
+    }
+
+    /// Runs one call into the component under the per-call fuel limit and
+    /// wall-clock safety net. Returns `None` if the call failed, which
+    /// disqualifies the bot.
+    fn call_guest<R>(
+        &mut self,
+        call: impl FnOnce(&Player, &mut Store<StoreData>) -> Result<R>,
+    ) -> Result<Option<R>> {
+        let budget = self.decision_fuel_budget();
+        self.store
+            .set_fuel(budget)
+            .context(HostError("Failed to set the bot's fuel budget"))?;
+        self.store.set_epoch_deadline(wall_clock_limit_ticks());
+
+        let result = call(&self.player, &mut self.store);
+
+        let consumed = budget
+            - self
+                .store
+                .get_fuel()
+                .context(HostError("Failed to read the bot's remaining fuel"))?;
+        let data = self.store.data_mut();
+        data.fuel_consumed += consumed;
+        if let Some(remaining) = &mut data.fuel_remaining {
+            *remaining -= consumed;
+        }
+
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => {
+                Err(e.context(WallClockLimitExceeded))
+            }
+            Err(e) => {
+                // A failed call leaves the instance unusable, so the bot sits
+                // out the rest of the run if the failure was its own doing
+                let Some(reason) = self.store.data().disqualification_for(&e) else {
+                    return Err(e);
+                };
+                tracing::warn!("Strategy disqualified ({reason}): {e:#}");
+                self.store.data_mut().disqualification = Some(reason);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Calls the component's `lifecycle` export, if it has one and is still
+    /// in the game
+    fn call_lifecycle(
+        &mut self,
+        call: impl FnOnce(&lifecycle::Guest, &mut Store<StoreData>) -> Result<()>,
+    ) -> Result<()> {
+        if self.player.lifecycle().is_none() || self.is_disqualified() {
+            return Ok(());
+        }
+        self.call_guest(|player, store| match player.lifecycle() {
+            Some(lifecycle) => call(lifecycle, store),
+            None => Ok(()),
+        })?;
+        Ok(())
+    }
+}
+
+impl Strategy for WasmStrategy {
+    fn should_roll(&mut self, state: &GameState) -> Result<bool> {
+        // Check if a resource limit was already hit
+        if self.is_disqualified() {
+            return Ok(false); // Force hold if disqualified
+        }
+
+        if !matches!(self.player, Player::V0_1(_)) {
+            self.store.data_mut().sync_history(state.turn_history);
+        }
+
+        let decision = self.call_guest(|player, store| match player {
+            Player::V0_1(player) => {
+                let state = v0_1::exports::pig_pen::player::strategy::GameState {
+                    current_player_index: state.current_player_index,
+                    current_banked_score: state.current_banked_score,
+                    current_total_score: state.current_total_score,
+                    all_players_banked_scores: state.all_players_banked_scores.to_vec(),
+                    turn_history: state.turn_history.to_vec(),
+                };
+                player
+                    .pig_pen_player_strategy()
+                    .call_should_roll(store, &state)
+            }
+            Player::V0_2(player) => {
+                let state = v0_2::exports::pig_pen::player::strategy::GameState {
+                    current_player_index: state.current_player_index,
+                    current_banked_score: state.current_banked_score,
+                    current_total_score: state.current_total_score,
+                    all_players_banked_scores: state.all_players_banked_scores.to_vec(),
+                    target_score: state.rules.target_score,
+                    history_len: state.turn_history.len() as u32,
+                };
+                player
+                    .pig_pen_player_strategy()
+                    .call_should_roll(store, &state)
+            }
+            Player::V0_3 { strategy, .. } => {
+                let state = strategy_v0_3::GameState {
+                    current_player_index: state.current_player_index,
+                    current_banked_score: state.current_banked_score,
+                    current_total_score: state.current_total_score,
+                    all_players_banked_scores: state.all_players_banked_scores.to_vec(),
+                    target_score: state.rules.target_score,
+                    history_len: state.turn_history.len() as u32,
+                };
+                strategy.call_should_roll(store, &state)
+            }
+        })?;
+
+        Ok(decision.unwrap_or(false)) // Force hold if disqualified
+    }
+
+    fn on_game_start(&mut self, num_players: usize, seat: usize) -> Result<()> {
+        self.store.data_mut().history.clear();
+        self.call_lifecycle(|lifecycle, store| {
+            lifecycle.call_on_game_start(store, num_players as u32, seat as u32)
+        })
+    }
+
+    fn on_turn_end(
+        &mut self,
+        player: usize,
+        outcome: TurnOutcome,
+        turn_history: &[TurnHistoryEntry],
+    ) -> Result<()> {
+        let outcome = match outcome {
+            TurnOutcome::Banked { score } => WitTurnOutcome::Banked(score),
+            TurnOutcome::Bust { reason, score } => WitTurnOutcome::Bust(WitBust {
+                reason: match reason {
+                    BustReason::SnakeEyes => WitBustReason::SnakeEyes,
+                    BustReason::Seven => WitBustReason::Seven,
+                    BustReason::SingleOne => WitBustReason::SingleOne,
+                    BustReason::ThreeDoubles => WitBustReason::ThreeDoubles,
+                    BustReason::ExactTarget => WitBustReason::ExactTarget,
+                },
+                score,
+            }),
+            TurnOutcome::Disqualified => WitTurnOutcome::Disqualified,
+        };
+        self.store.data_mut().sync_history(turn_history);
+        self.call_lifecycle(|lifecycle, store| {
+            lifecycle.call_on_turn_end(store, player as u32, outcome)
+        })
+    }
+
+    fn on_game_end(
+        &mut self,
+        final_scores: &[u32],
+        payouts: &[i64],
+        turn_history: &[TurnHistoryEntry],
+    ) -> Result<()> {
+        self.store.data_mut().sync_history(turn_history);
+        self.call_lifecycle(|lifecycle, store| {
+            lifecycle.call_on_game_end(store, final_scores, payouts)
+        })
+    }
+
+    fn reset(&mut self, game_index: u64) -> Result<()> {
+        if self.is_disqualified() {
+            return Ok(());
+        }
+
+        // Each instance draws from its own reproducible random stream, tied to
+        // the game it starts at rather than to how many came before it
+        let previous = self.store.data();
+        let data = StoreData {
+            peak_memory_bytes: previous.peak_memory_bytes,
+            memory_limit: previous.memory_limit,
+            fuel_per_decision: previous.fuel_per_decision,
+            fuel_remaining: previous.fuel_remaining,
+            fuel_consumed: previous.fuel_consumed,
+            ..StoreData::new(derive_seed(self.seed, game_index))
+        };
+
+        let mut store =
+            new_store(self.store.engine(), data).context(HostError("Failed to create a store"))?;
+        match self.indices.instantiate(&self.instance_pre, &mut store) {
+            Ok(player) => {
+                self.store = store;
+                self.player = player;
+            }
+            Err(e) => {
+                let Some(reason) = store.data().disqualification_for(&e) else {
+                    return Err(e.context("Failed to start a fresh instance of the bot"));
+                };
+                tracing::warn!("Strategy disqualified ({reason}) while resetting: {e:#}");
+                self.store.data_mut().disqualification = Some(reason);
+            }
+        }
+        Ok(())
+    }
+
+    fn peak_memory_bytes(&self) -> u64 {
+        self.store.data().peak_memory_bytes
+    }
+
+    fn set_memory_limit(&mut self, limit_bytes: u64) {
+        self.store.data_mut().memory_limit = Some(limit_bytes);
+    }
+
+    fn set_fuel_limits(&mut self, per_decision: Option<u64>, per_simulation: Option<u64>) {
+        let data = self.store.data_mut();
+        data.fuel_per_decision = per_decision;
+        data.fuel_remaining =
+            per_simulation.map(|budget| budget.saturating_sub(data.fuel_consumed));
+    }
+
+    fn fuel_consumed(&self) -> u64 {
+        self.store.data().fuel_consumed
+    }
+
+    fn disqualification_reason(&self) -> Option<DisqualificationReason> {
+        let data = self.store.data();
+        if data.memory_limit_hit {
+            Some(DisqualificationReason::MemoryLimit)
+        } else {
+            data.disqualification
+        }
+    }
+}
+
+/// Creates a store for one instance of a component
+fn new_store(engine: &Engine, data: StoreData) -> Result<Store<StoreData>> {
+    let mut store = Store::new(engine, data);
+    store.limiter(|tracker| tracker);
+    // Instantiation isn't charged against the bot's CPU budget
+    store.set_fuel(u64::MAX)?;
+    store.set_epoch_deadline(INSTANTIATION_DEADLINE_TICKS);
+    Ok(store)
+}
+
+pub fn create_engine() -> Result<Engine> {
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    config.consume_fuel(true);
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config)?;
+
+    // Advance the epoch in the background so decision deadlines expire; the
+    // thread exits once the engine has been dropped
+    let weak = engine.weak();
+    thread::Builder::new()
+        .name("wasm-epoch".to_string())
+        .spawn(move || {
+            while let Some(engine) = weak.upgrade() {
+                engine.increment_epoch();
+                drop(engine);
+                thread::sleep(EPOCH_TICK);
+            }
+        })?;
+
+    Ok(engine)
+}
+
+/// `DECISION_WALL_CLOCK_LIMIT` in epoch ticks
+fn wall_clock_limit_ticks() -> u64 {
+    (DECISION_WALL_CLOCK_LIMIT.as_millis() / EPOCH_TICK.as_millis()) as u64
+}
+
+/// Reads and compiles a component from disk, through `cache` when given
+pub fn load_component(
+    engine: &Engine,
+    cache: Option<&ComponentCache>,
+    wasm_path: &str,
+) -> Result<Component> {
+    let wasm_bytes =
+        fs::read(wasm_path).with_context(|| format!("Failed to read WASM file: {}", wasm_path))?;
+    match cache {
+        Some(cache) => cache.component(engine, &wasm_bytes),
+        None => {
+            Component::from_binary(engine, &wasm_bytes).context("Failed to compile WASM component")
+        }
+    }
+}